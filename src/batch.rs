@@ -0,0 +1,420 @@
+//! Plays many games of a board, spread over threads, and gathers their results.
+
+use crate::boards::{Board, Special};
+use crate::dice::derive_seed;
+use crate::game::Game;
+use crate::sim::{Rules, Sim};
+use crate::stats::{BatchStats, Distribution, Heatmap};
+use crate::trace::{game_events, Event};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::{fmt, thread};
+
+/// Stats over a whole batch of games
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MultiSimResult {
+    /// Fewest rolls the winner of a game needed. Abandoned games don't count, so the rolls
+    /// stats are None if every game was
+    pub min_rolls: Option<usize>,
+    /// Mean rolls the winner of a game needed
+    pub avg_rolls: Option<f64>,
+    /// Most rolls the winner of a game needed
+    pub max_rolls: Option<usize>,
+    /// Least total distance climbed up ladders in a game, by one player. The stats from here
+    /// down to the distributions count every player, winner or not
+    pub min_climb: usize,
+    /// Mean total distance climbed up ladders in a game
+    pub avg_climb: f64,
+    /// Most total distance climbed up ladders in a game
+    pub max_climb: usize,
+    /// Least total distance slid down snakes in a game
+    pub min_slide: usize,
+    /// Mean total distance slid down snakes in a game
+    pub avg_slide: f64,
+    /// Most total distance slid down snakes in a game
+    pub max_slide: usize,
+    /// Greatest climb in a single turn, INCLUDING re-rolls and chains
+    pub biggest_turn_climb: usize,
+    /// Greatest slide in a single turn, INCLUDING re-rolls and chains
+    pub biggest_turn_slide: usize,
+    /// Most rolls in a turn, dice by dice, see sim::cmp_turns
+    pub longest_turn: Vec<Vec<usize>>,
+    /// Fewest lucky rolls in a game, see sim::calc_lucky_spaces
+    pub min_lucky_rolls: usize,
+    /// Mean lucky rolls in a game
+    pub avg_lucky_rolls: f64,
+    /// Most lucky rolls in a game
+    pub max_lucky_rolls: usize,
+    /// Fewest unlucky rolls in a game, see sim::calc_lucky_spaces
+    pub min_unlucky_rolls: usize,
+    /// Mean unlucky rolls in a game
+    pub avg_unlucky_rolls: f64,
+    /// Most unlucky rolls in a game
+    pub max_unlucky_rolls: usize,
+    /// Spread of the rolls the winner needed
+    pub rolls_distribution: Option<Distribution>,
+    /// Spread of the total climb in a game
+    pub climb_distribution: Distribution,
+    /// Spread of the total slide in a game
+    pub slide_distribution: Distribution,
+    /// Spread of the lucky rolls in a game
+    pub lucky_rolls_distribution: Distribution,
+    /// Spread of the unlucky rolls in a game
+    pub unlucky_rolls_distribution: Distribution,
+    /// Hit max_turns before winning. Left out of the rolls stats, still in the others
+    pub abandoned_games: usize,
+    /// Chance of winning for each seat, first player first
+    pub seat_win_rates: Vec<f64>,
+    /// Average times a player sets off each kind of special square
+    pub special_uses: BTreeMap<Special, f64>,
+    /// Where players landed and which routes they took
+    pub heatmap: Heatmap,
+    /// Master seed for the batch
+    pub seed: Option<u64>,
+    /// Seeds to replay the record setting games
+    pub extreme_seeds: ExtremeSeeds,
+}
+
+/// Why a batch has no stats
+#[derive(Debug, PartialEq)]
+pub enum BatchError {
+    /// Not a single game was played
+    NoGames,
+    /// The rules have nobody to play
+    NoPlayers,
+}
+impl std::error::Error for BatchError {}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BatchError::NoGames => write!(f, "Need at least one game to gather stats"),
+            BatchError::NoPlayers => write!(f, "Need at least one player per game"),
+        }
+    }
+}
+
+/// Seeds of the games that set each record, see play_game
+#[derive(Debug, PartialEq, Default, Serialize)]
+pub struct ExtremeSeeds {
+    /// Game with the fewest rolls
+    pub min_rolls: Option<u64>,
+    /// Game with the most rolls
+    pub max_rolls: Option<u64>,
+    /// Game with the least climb
+    pub min_climb: Option<u64>,
+    /// Game with the most climb
+    pub max_climb: Option<u64>,
+    /// Game with the least slide
+    pub min_slide: Option<u64>,
+    /// Game with the most slide
+    pub max_slide: Option<u64>,
+    /// Game with the biggest climb in one turn
+    pub biggest_turn_climb: Option<u64>,
+    /// Game with the biggest slide in one turn
+    pub biggest_turn_slide: Option<u64>,
+    /// Game with the longest turn
+    pub longest_turn: Option<u64>,
+    /// Game with the fewest lucky rolls
+    pub min_lucky_rolls: Option<u64>,
+    /// Game with the most lucky rolls
+    pub max_lucky_rolls: Option<u64>,
+    /// Game with the fewest unlucky rolls
+    pub min_unlucky_rolls: Option<u64>,
+    /// Game with the most unlucky rolls
+    pub max_unlucky_rolls: Option<u64>,
+}
+
+impl MultiSimResult {
+    /// Each Sim counts as a single player game, an error if there are none
+    pub fn from_sims(sims: &[Sim]) -> Result<MultiSimResult, BatchError> {
+        let mut stats = BatchStats::default();
+        for sim in sims {
+            stats.add_sim(sim);
+        }
+        stats.result()
+    }
+
+    /// Rolls cover the winner of each game, the other stats every player. An error if there are no games
+    pub fn from_games(games: &[Game]) -> Result<MultiSimResult, BatchError> {
+        let mut stats = BatchStats::default();
+        for game in games {
+            stats.add_game(game);
+        }
+        stats.result()
+    }
+}
+
+/// Play one game to the end. The same seed always plays out the same way
+pub fn play_game(board: &Board, rules: &Rules, seed: u64) -> Game {
+    new_game(board, rules, seed, false)
+}
+
+/// play_game, keeping every roll, in the order they happened
+pub fn trace_game(board: &Board, rules: &Rules, seed: u64) -> (Game, Vec<Event>) {
+    let game = new_game(board, rules, seed, true);
+    let events = game_events(&game);
+    (game, events)
+}
+
+fn new_game(board: &Board, rules: &Rules, seed: u64, trace: bool) -> Game {
+    let players = (0..rules.players)
+        .map(|seat| {
+            let rng = StdRng::seed_from_u64(derive_seed(seed, seat as u64));
+            let mut sim = Sim::with_rules(board.clone(), rules.clone(), rules.die.roller(rng));
+            sim.seed = Some(seed);
+            sim.seat = seat;
+            if trace {
+                sim.record_trace();
+            }
+            sim
+        })
+        .collect();
+    let mut game = Game::new(players);
+    game.run();
+    game
+}
+
+/// Play count games, split across threads. Each game's seed depends only on its index,
+/// so the result is the same whatever the number of threads. An error if count is 0
+pub fn run_sim_batch(
+    board: Board,
+    rules: Rules,
+    count: usize,
+    seed: u64,
+    threads: usize,
+) -> Result<MultiSimResult, BatchError> {
+    if rules.players == 0 {
+        return Err(BatchError::NoPlayers);
+    }
+    let chunk_size = count.div_ceil(threads.max(1)).max(1);
+    let stats = thread::scope(|scope| {
+        let workers: Vec<_> = (0..count)
+            .step_by(chunk_size)
+            .map(|start| {
+                let (board, rules) = (&board, &rules);
+                scope.spawn(move || {
+                    // Only keep the running stats, not the games
+                    let mut stats = BatchStats::default();
+                    for i in start..count.min(start + chunk_size) {
+                        stats.add_game(&play_game(board, rules, derive_seed(seed, i as u64)));
+                    }
+                    stats
+                })
+            })
+            .collect();
+        let mut stats = BatchStats::default();
+        for worker in workers {
+            stats.merge(&worker.join().unwrap());
+        }
+        stats
+    });
+    Ok(MultiSimResult {
+        seed: Some(seed),
+        ..stats.result()?
+    })
+}
+
+#[cfg(test)]
+mod tests_stats {
+    use super::*;
+    use crate::boards::{blank, canon_board};
+    use crate::dice::Unrollable;
+    use crate::stats::{Bucket, Tally};
+
+    fn min_avg_max(sequence: Vec<usize>) -> Option<(usize, f64, usize)> {
+        let mut tally = Tally::default();
+        for value in sequence {
+            tally.add(value, None);
+        }
+        tally.min_avg_max()
+    }
+
+    #[test]
+    fn test_min_max_average_empty() {
+        assert!(min_avg_max(vec![]).is_none());
+    }
+    #[test]
+    fn test_min_max_average_singleton() {
+        assert_eq!(min_avg_max(vec![5]).unwrap(), (5, 5.0, 5));
+    }
+    #[test]
+    fn test_min_max_average_fraction() {
+        assert_eq!(min_avg_max(vec![8, 0, 3]).unwrap(), (0, 11.0 / 3.0, 8));
+    }
+    #[test]
+    fn test_empty_multi_sim_result() {
+        let zero = || Distribution {
+            std_dev: 0.0,
+            median: 0,
+            p90: 0,
+            p95: 0,
+            p99: 0,
+            histogram: vec![Bucket {
+                from: 0,
+                to: 0,
+                count: 1,
+            }],
+        };
+        let b = blank(100);
+        let rng = Box::new(Unrollable {});
+        let sim = Sim::new(b, rng);
+        let result: MultiSimResult = MultiSimResult::from_sims(&[sim]).unwrap();
+        assert_eq!(
+            result,
+            MultiSimResult {
+                min_rolls: None,
+                avg_rolls: None,
+                max_rolls: None,
+                min_climb: 0,
+                avg_climb: 0.0,
+                max_climb: 0,
+                min_slide: 0,
+                avg_slide: 0.0,
+                max_slide: 0,
+                biggest_turn_climb: 0,
+                biggest_turn_slide: 0,
+                longest_turn: vec![],
+                min_lucky_rolls: 0,
+                avg_lucky_rolls: 0.0,
+                max_lucky_rolls: 0,
+                min_unlucky_rolls: 0,
+                avg_unlucky_rolls: 0.0,
+                max_unlucky_rolls: 0,
+                rolls_distribution: None,
+                climb_distribution: zero(),
+                slide_distribution: zero(),
+                lucky_rolls_distribution: zero(),
+                unlucky_rolls_distribution: zero(),
+                abandoned_games: 0,
+                seat_win_rates: vec![0.0],
+                special_uses: BTreeMap::new(),
+                heatmap: Heatmap {
+                    landed_on: vec![0.0; 101],
+                    ended_on: vec![0.0; 101],
+                    routes: vec![],
+                },
+                seed: None,
+                extreme_seeds: ExtremeSeeds::default(),
+            }
+        )
+    }
+    #[test]
+    fn test_sim_batch() {
+        let results = run_sim_batch(canon_board(), Rules::default(), 10, 1, 2).unwrap();
+        let (min_rolls, max_rolls) = (results.min_rolls.unwrap(), results.max_rolls.unwrap());
+        assert!(min_rolls > 0); // Must roll once in order to win
+        assert!(results.min_lucky_rolls >= 1); // Winning is a lucky roll
+        assert_eq!(results.abandoned_games, 0);
+        let rolls = results.rolls_distribution.unwrap();
+        assert!(min_rolls <= rolls.median && rolls.median <= rolls.p90);
+        assert!(rolls.p90 <= rolls.p95 && rolls.p95 <= rolls.p99 && rolls.p99 <= max_rolls);
+        assert_eq!(rolls.histogram.iter().map(|b| b.count).sum::<usize>(), 10);
+    }
+    #[test]
+    fn test_sim_batch_heatmap() {
+        let heatmap = run_sim_batch(canon_board(), Rules::default(), 100, 1, 2)
+            .unwrap()
+            .heatmap;
+        // Everybody finishes, nobody rests at the foot of a ladder
+        assert_eq!(heatmap.landed_on[100], 1.0);
+        assert_eq!(heatmap.ended_on[100], 1.0);
+        assert_eq!(heatmap.ended_on[4], 0.0);
+        assert!(heatmap.landed_on[4] > 0.0);
+        assert_eq!(heatmap.routes.len(), 15);
+        assert_eq!((heatmap.routes[0].from, heatmap.routes[0].to), (4, 25));
+        assert_eq!(heatmap.routes[0].chance, heatmap.landed_on[4]);
+    }
+    #[test]
+    fn test_sim_batch_specials() {
+        let specials = [(30, Special::BackToStart), (60, Special::ExtraRoll)];
+        let board = Board::with_specials(100, canon_board().routes, specials.into()).unwrap();
+        let results = run_sim_batch(board, Rules::default(), 100, 1, 2).unwrap();
+        assert_eq!(results.special_uses.len(), 2);
+        assert!(results.special_uses[&Special::BackToStart] > 0.0);
+        assert!(results.special_uses[&Special::ExtraRoll] > 0.0);
+    }
+    #[test]
+    fn test_sim_batch_abandoned() {
+        // Winning a blank board in one turn takes sixteen 6s in a row
+        let rules = Rules {
+            max_turns: Some(1),
+            ..Rules::default()
+        };
+        let results = run_sim_batch(blank(100), rules, 10, 1, 2).unwrap();
+        assert_eq!(results.abandoned_games, 10);
+        assert_eq!(results.seat_win_rates, vec![0.0]);
+        assert_eq!(
+            (results.min_rolls, results.rolls_distribution),
+            (None, None)
+        );
+    }
+    #[test]
+    fn test_sim_batch_some_abandoned() {
+        let rules = Rules {
+            max_turns: Some(20),
+            ..Rules::default()
+        };
+        let results = run_sim_batch(canon_board(), rules.clone(), 100, 1, 2).unwrap();
+        let won = 100 - results.abandoned_games;
+        assert!(won > 0 && results.abandoned_games > 0);
+        // Only the won games count towards the rolls to win
+        let rolls = results.rolls_distribution.unwrap();
+        assert_eq!(rolls.histogram.iter().map(|b| b.count).sum::<usize>(), won);
+        let seed = results.extreme_seeds.max_rolls.unwrap();
+        let game = play_game(&canon_board(), &rules, seed);
+        assert!(game.winner.is_some());
+    }
+    #[test]
+    fn test_sim_batch_seeded() {
+        let results = run_sim_batch(canon_board(), Rules::default(), 50, 42, 1).unwrap();
+        assert_eq!(
+            results,
+            run_sim_batch(canon_board(), Rules::default(), 50, 42, 1).unwrap()
+        );
+        assert_ne!(
+            results,
+            run_sim_batch(canon_board(), Rules::default(), 50, 43, 1).unwrap()
+        );
+        // Same games whatever the number of threads
+        assert_eq!(
+            results,
+            run_sim_batch(canon_board(), Rules::default(), 50, 42, 7).unwrap()
+        );
+        // Replay the longest game on its own
+        let seed = results.extreme_seeds.max_rolls.unwrap();
+        let game = play_game(&canon_board(), &Rules::default(), seed);
+        assert_eq!(Some(game.players[0].roll_count), results.max_rolls);
+    }
+    #[test]
+    fn test_sim_batch_empty() {
+        let results = run_sim_batch(canon_board(), Rules::default(), 0, 1, 2);
+        assert_eq!(results, Err(BatchError::NoGames));
+        assert_eq!(MultiSimResult::from_games(&[]), Err(BatchError::NoGames));
+        let rules = Rules {
+            players: 0,
+            ..Rules::default()
+        };
+        let results = run_sim_batch(canon_board(), rules, 1, 1, 1);
+        assert_eq!(results, Err(BatchError::NoPlayers));
+    }
+    #[test]
+    fn test_sim_batch_multiplayer() {
+        let rules = Rules {
+            players: 3,
+            ..Rules::default()
+        };
+        let results = run_sim_batch(canon_board(), rules.clone(), 10, 1, 2).unwrap();
+        assert_eq!(results.seat_win_rates.len(), 3);
+        // Only the winners' rolls count, one per game
+        let rolls = &results.rolls_distribution.unwrap().histogram;
+        assert_eq!(rolls.iter().map(|b| b.count).sum::<usize>(), 10);
+        let seed = results.extreme_seeds.min_rolls.unwrap();
+        let game = play_game(&canon_board(), &rules, seed);
+        let winner = &game.players[game.winner.unwrap()];
+        assert_eq!(Some(winner.roll_count), results.min_rolls);
+        assert!((results.seat_win_rates.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+}
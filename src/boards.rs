@@ -0,0 +1,587 @@
+//! The board: its size, snakes and ladders, special squares and random routes.
+
+use crate::error::ConfigError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// What happens when a roll would take the token past the last square
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Overshoot {
+    #[default]
+    /// Illegal move, the token doesn't move
+    Stay,
+    /// Count back from the last square by the excess, e.g. 98 + 5 on 100 squares lands on 97
+    Bounce,
+    /// Any roll past the end wins
+    Finish,
+}
+
+impl Overshoot {
+    /// The square a roll of value from square lands on, before any snake or ladder.
+    /// None if the token stays put
+    pub fn land(self, size: usize, square: usize, value: usize) -> Option<usize> {
+        let target = square + value;
+        if target <= size {
+            return Some(target);
+        }
+        match self {
+            Overshoot::Stay => None,
+            Overshoot::Bounce => Some(size.saturating_sub(target - size)),
+            Overshoot::Finish => Some(size),
+        }
+    }
+}
+
+/// A square that does something other than move the token along a snake or ladder.
+/// It takes effect where a roll finishes, after any snakes and ladders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Special {
+    /// Skip the player's next turn
+    LoseTurn,
+    /// Roll again this turn, whatever the die says
+    ExtraRoll,
+    /// Roll again and move back that far, which ends the turn
+    Backwards,
+    /// Straight back to square 0
+    BackToStart,
+    /// Swap squares with whoever is furthest ahead at the end of the turn, which ends it
+    SwapWithLeader,
+}
+
+/// A snake or ladder that doesn't always fire, or that could go to one of several squares
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomRoute {
+    /// Of firing at all, from 0 to 1
+    pub chance: f64,
+    /// Each destination and its weight, relative to the others
+    pub to: Vec<(usize, f64)>,
+}
+
+impl RandomRoute {
+    /// Where a token that lands on from ends up, and the chance of it. The last one is staying put,
+    /// so Roll::choose can pick one by index
+    pub fn outcomes(&self, from: usize) -> Vec<(usize, f64)> {
+        let total: f64 = self.to.iter().map(|&(_, w)| w).sum();
+        let mut outcomes: Vec<(usize, f64)> = self
+            .to
+            .iter()
+            .map(|&(to, w)| (to, self.chance * w / total))
+            .collect();
+        outcomes.push((from, 1.0 - self.chance));
+        outcomes
+    }
+
+    /// Explains what's wrong with the odds
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.chance) {
+            return Err(format!("chance {} isn't between 0 and 1", self.chance));
+        }
+        if self.to.is_empty() {
+            return Err("nowhere to go".to_string());
+        }
+        if self.to.iter().any(|&(_, w)| !w.is_finite() || w < 0.0) {
+            return Err("weights can't be negative or infinite".to_string());
+        }
+        if self.to.iter().all(|&(_, w)| w == 0.0) {
+            return Err("every weight is 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The squares numbered 1 to size, and the snakes and ladders between them.
+/// Players start off the board on square 0 and must land exactly on size to win
+#[derive(Debug, Clone)]
+pub struct Board {
+    /// Number of the last square
+    pub size: usize,
+    /// Snakes AND Ladders in Source: Destination order
+    pub routes: HashMap<usize, usize>,
+    /// The special squares, by square
+    pub specials: HashMap<usize, Special>,
+    /// The random routes, by the square they start from
+    pub random_routes: HashMap<usize, RandomRoute>,
+}
+
+impl Board {
+    /// Checks every route starts and ends on the board, and that they don't form a loop
+    pub fn new(size: usize, routes: HashMap<usize, usize>) -> Result<Board, ConfigError> {
+        Board::with_specials(size, routes, HashMap::new())
+    }
+
+    /// Board::new, with special squares. They must be on the board, and not where a snake or ladder starts
+    pub fn with_specials(
+        size: usize,
+        routes: HashMap<usize, usize>,
+        specials: HashMap<usize, Special>,
+    ) -> Result<Board, ConfigError> {
+        // Do some validation first
+        for (&from, &to) in routes.iter() {
+            if (from == 0) | (from >= size) | (to > size) {
+                return Err(ConfigError::OutOfRange { from, to, size });
+            }
+            if from == to {
+                return Err(ConfigError::SelfLoop(from));
+            }
+        }
+        let board = Board {
+            size,
+            routes,
+            specials: HashMap::new(),
+            random_routes: HashMap::new(),
+        };
+        if let Some(cycle) = find_cycle(&board.edges()) {
+            return Err(ConfigError::RouteCycle(cycle));
+        }
+        board.add_specials(specials)
+    }
+
+    /// Add random routes to the board. Like other routes, they must fit on the board, not loop,
+    /// and not start where another route starts or on a special square
+    pub fn with_random_routes(
+        self,
+        random_routes: HashMap<usize, RandomRoute>,
+    ) -> Result<Board, ConfigError> {
+        let size = self.size;
+        for (&from, route) in random_routes.iter() {
+            for &(to, _) in route.to.iter() {
+                if from == 0 || from >= size || to > size {
+                    return Err(ConfigError::OutOfRange { from, to, size });
+                }
+                if from == to {
+                    return Err(ConfigError::SelfLoop(from));
+                }
+            }
+            if self.routes.contains_key(&from) {
+                return Err(ConfigError::DuplicateSource(from));
+            }
+            if self.specials.contains_key(&from) {
+                return Err(ConfigError::SpecialOnRoute(from));
+            }
+            route
+                .validate()
+                .map_err(|e| ConfigError::BadOdds(from, e))?;
+        }
+        let board = Board {
+            random_routes,
+            ..self
+        };
+        if let Some(cycle) = find_cycle(&board.edges()) {
+            return Err(ConfigError::RouteCycle(cycle));
+        }
+        Ok(board)
+    }
+
+    fn add_specials(self, specials: HashMap<usize, Special>) -> Result<Board, ConfigError> {
+        let size = self.size;
+        for &square in specials.keys() {
+            if square == 0 || square >= size {
+                return Err(ConfigError::SpecialOffBoard { square, size });
+            }
+            if self.routes.contains_key(&square) {
+                return Err(ConfigError::SpecialOnRoute(square));
+            }
+        }
+        Ok(Board { specials, ..self })
+    }
+
+    /// Every square each route can go to, by the square it starts from
+    fn edges(&self) -> HashMap<usize, Vec<usize>> {
+        let mut edges: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (&from, &to) in self.routes.iter() {
+            edges.entry(from).or_default().push(to);
+        }
+        for (&from, route) in self.random_routes.iter() {
+            edges
+                .entry(from)
+                .or_default()
+                .extend(route.to.iter().map(|&(to, _)| to));
+        }
+        edges
+    }
+
+    /// (from, to) of every snake and ladder, in order. A random route is there once per destination
+    pub fn all_routes(&self) -> Vec<(usize, usize)> {
+        let mut routes: Vec<(usize, usize)> = self
+            .edges()
+            .into_iter()
+            .flat_map(|(from, to)| to.into_iter().map(move |to| (from, to)))
+            .collect();
+        routes.sort_unstable();
+        routes.dedup();
+        routes
+    }
+
+    /// The chance that landing on from takes the route straight to to, 1 unless it's random
+    pub fn route_chance(&self, from: usize, to: usize) -> f64 {
+        match self.random_routes.get(&from) {
+            Some(route) => route
+                .outcomes(from)
+                .iter()
+                .filter(|&&(square, _)| square == to && square != from)
+                .map(|&(_, p)| p)
+                .sum(),
+            None if self.routes.get(&from) == Some(&to) => 1.0,
+            None => 0.0,
+        }
+    }
+
+    /// Where a token that lands on this square can end up after following every snake and ladder,
+    /// and the chance of each, in order of square
+    pub fn destinations(&self, square: usize) -> Vec<(usize, f64)> {
+        let mut ended: BTreeMap<usize, f64> = BTreeMap::new();
+        let mut moving = vec![(square, 1.0)];
+        // An acyclic chain can't be longer than this, guards against hand-built boards
+        for _ in 0..=self.routes.len() + self.random_routes.len() {
+            let mut next = vec![];
+            for (square, p) in moving {
+                if let Some(&to) = self.routes.get(&square) {
+                    next.push((to, p));
+                } else if let Some(route) = self.random_routes.get(&square) {
+                    for (to, q) in route.outcomes(square) {
+                        if to == square {
+                            *ended.entry(to).or_default() += p * q; // Stayed put
+                        } else {
+                            next.push((to, p * q));
+                        }
+                    }
+                } else {
+                    *ended.entry(square).or_default() += p;
+                }
+            }
+            moving = next;
+        }
+        for (square, p) in moving {
+            *ended.entry(square).or_default() += p;
+        }
+        ended.into_iter().filter(|&(_, p)| p > 0.0).collect()
+    }
+}
+
+impl Board {
+    /// Check the last square can be reached from every square a player can get to.
+    /// die_values are the totals the dice can roll, overshoot says what rolling past the end does
+    pub fn check_winnable(
+        &self,
+        die_values: &[usize],
+        overshoot: Overshoot,
+    ) -> Result<(), ConfigError> {
+        let reach = |square: usize| {
+            self.destinations(square)
+                .into_iter()
+                .map(|(square, _)| square)
+        };
+        let moves = |square: usize| {
+            let mut moves = vec![];
+            for square in die_values
+                .iter()
+                .filter_map(|&v| overshoot.land(self.size, square, v))
+                .flat_map(reach)
+            {
+                // Swaps only ever trade squares players could reach anyway
+                match self.specials.get(&square) {
+                    Some(Special::BackToStart) => moves.push(0),
+                    Some(Special::Backwards) => moves.extend(
+                        die_values
+                            .iter()
+                            .flat_map(|&v| reach(square.saturating_sub(v))),
+                    ),
+                    _ => moves.push(square),
+                }
+            }
+            moves
+        };
+        // Find every square a player can be on, and how they can get there
+        let mut reachable: HashSet<usize> = HashSet::from([0]);
+        let mut sources: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut queue = vec![0];
+        while let Some(square) = queue.pop() {
+            for next in moves(square) {
+                sources.entry(next).or_default().push(square);
+                if reachable.insert(next) {
+                    queue.push(next);
+                }
+            }
+        }
+        // Walk backwards from the last square
+        let mut winnable: HashSet<usize> = HashSet::from([self.size]);
+        let mut queue = vec![self.size];
+        while let Some(square) = queue.pop() {
+            for &prev in sources.get(&square).into_iter().flatten() {
+                if winnable.insert(prev) {
+                    queue.push(prev);
+                }
+            }
+        }
+        match reachable.difference(&winnable).min() {
+            Some(&stuck) => Err(ConfigError::Unwinnable(stuck)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the squares of a loop of snakes and ladders, if there is one
+/// e.g. a ladder 4->25 and a snake 25->4 gives [4, 25]
+fn find_cycle(edges: &HashMap<usize, Vec<usize>>) -> Option<Vec<usize>> {
+    /// Depth first, path is the way here from the start
+    fn visit(
+        square: usize,
+        edges: &HashMap<usize, Vec<usize>>,
+        path: &mut Vec<usize>,
+        checked: &mut HashSet<usize>,
+    ) -> Option<Vec<usize>> {
+        if let Some(i) = path.iter().position(|&s| s == square) {
+            return Some(path[i..].to_vec());
+        }
+        if checked.contains(&square) {
+            return None;
+        }
+        path.push(square);
+        let mut next = edges.get(&square).cloned().unwrap_or_default();
+        next.sort_unstable();
+        for to in next {
+            if let Some(cycle) = visit(to, edges, path, checked) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        checked.insert(square);
+        None
+    }
+
+    let mut starts: Vec<usize> = edges.keys().copied().collect();
+    starts.sort_unstable(); // Report the same cycle every time
+    let mut checked: HashSet<usize> = HashSet::new();
+    starts
+        .into_iter()
+        .find_map(|start| visit(start, edges, &mut vec![], &mut checked))
+}
+
+/// A board with no snakes or ladders
+#[allow(dead_code)]
+pub(crate) fn blank(size: usize) -> Board {
+    Board::new(size, HashMap::new()).unwrap()
+}
+
+/// Returns the board from the prompt
+#[allow(dead_code)]
+pub(crate) fn canon_board() -> Board {
+    Board::new(
+        100,
+        HashMap::from([
+            // snakes go down
+            (27, 5),
+            (40, 3),
+            (43, 18),
+            (54, 31),
+            (66, 45),
+            (76, 58),
+            (89, 53),
+            (99, 41),
+            // ladders go up
+            (4, 25),
+            (13, 46),
+            (33, 49),
+            (42, 63),
+            (50, 69),
+            (62, 81),
+            (74, 92),
+        ]),
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::boards::*;
+
+    #[test]
+    fn test_blank() {
+        let _ = blank(100);
+    }
+    #[test]
+    fn test_canon_board() {
+        let _ = canon_board();
+    }
+    #[test]
+    fn test_canon_board_winnable() {
+        assert!(canon_board()
+            .check_winnable(&[1, 2, 3, 4, 5, 6], Overshoot::Stay)
+            .is_ok());
+    }
+    #[test]
+    fn test_unwinnable() {
+        // Every square in reach of the finish has a snake back to 1
+        let snakes = (94..100).map(|from| (from, 1)).collect();
+        let board = Board::new(100, snakes).unwrap();
+        assert_eq!(
+            board.check_winnable(&[1, 2, 3, 4, 5, 6], Overshoot::Stay),
+            Err(ConfigError::Unwinnable(0))
+        );
+        // Only even rolls, so odd squares never finish
+        let board = Board::new(10, HashMap::from([(2, 3)])).unwrap();
+        assert_eq!(
+            board
+                .check_winnable(&[2, 4], Overshoot::Stay)
+                .unwrap_err()
+                .to_string(),
+            "Board can't be won after reaching square 3"
+        );
+    }
+    #[test]
+    fn test_winnable_overshoot() {
+        // Only 4s, so square 8 overshoots 10
+        let board = Board::new(10, HashMap::new()).unwrap();
+        assert!(board.check_winnable(&[4], Overshoot::Stay).is_err());
+        // 8 bounces back to 8, no way out
+        assert!(board.check_winnable(&[4], Overshoot::Bounce).is_err());
+        assert!(board.check_winnable(&[4], Overshoot::Finish).is_ok());
+        // 8 + 3 bounces back to 9, and 9 + 3 back to 8
+        assert!(board.check_winnable(&[3], Overshoot::Bounce).is_err());
+        assert!(board.check_winnable(&[3, 4], Overshoot::Bounce).is_ok());
+    }
+
+    #[test]
+    fn test_specials() {
+        let specials = HashMap::from([(3, Special::LoseTurn), (6, Special::BackToStart)]);
+        assert!(Board::with_specials(10, HashMap::from([(2, 5)]), specials).is_ok());
+        let result = Board::with_specials(
+            10,
+            HashMap::new(),
+            HashMap::from([(10, Special::ExtraRoll)]),
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            ConfigError::SpecialOffBoard {
+                square: 10,
+                size: 10
+            }
+        );
+        let result = Board::with_specials(
+            10,
+            HashMap::from([(4, 8)]),
+            HashMap::from([(4, Special::Backwards)]),
+        );
+        assert_eq!(result.unwrap_err(), ConfigError::SpecialOnRoute(4));
+    }
+
+    #[test]
+    fn test_winnable_specials() {
+        // Only 2s, and 8 sends every token back to the start
+        let specials = HashMap::from([(8, Special::BackToStart)]);
+        let board = Board::with_specials(10, HashMap::new(), specials).unwrap();
+        assert_eq!(
+            board.check_winnable(&[2], Overshoot::Stay),
+            Err(ConfigError::Unwinnable(0))
+        );
+        assert!(board.check_winnable(&[1, 2], Overshoot::Stay).is_ok());
+        // Every 2 from 2 lands on 4 and goes back to 2
+        let specials = HashMap::from([(4, Special::Backwards)]);
+        let board = Board::with_specials(10, HashMap::new(), specials).unwrap();
+        assert!(board.check_winnable(&[2], Overshoot::Stay).is_err());
+        assert!(board.check_winnable(&[1, 2], Overshoot::Stay).is_ok());
+    }
+
+    #[test]
+    fn test_overshoot_land() {
+        assert_eq!(Overshoot::Stay.land(100, 98, 2), Some(100));
+        assert_eq!(Overshoot::Stay.land(100, 98, 5), None);
+        assert_eq!(Overshoot::Bounce.land(100, 98, 5), Some(97));
+        assert_eq!(Overshoot::Bounce.land(3, 2, 20), Some(0));
+        assert_eq!(Overshoot::Finish.land(100, 98, 5), Some(100));
+    }
+
+    #[test]
+    fn test_random_routes() {
+        let route = RandomRoute {
+            chance: 0.5,
+            to: vec![(2, 1.0), (15, 3.0)],
+        };
+        assert_eq!(route.outcomes(10), vec![(2, 0.125), (15, 0.375), (10, 0.5)]);
+        let board = Board::new(20, HashMap::from([(15, 18)]))
+            .unwrap()
+            .with_random_routes(HashMap::from([(10, route)]))
+            .unwrap();
+        // The ladder from 15 is followed too
+        assert_eq!(
+            board.destinations(10),
+            vec![(2, 0.125), (10, 0.5), (18, 0.375)]
+        );
+        assert_eq!(board.destinations(15), vec![(18, 1.0)]);
+        assert_eq!(board.all_routes(), vec![(10, 2), (10, 15), (15, 18)]);
+        assert_eq!(board.route_chance(10, 15), 0.375);
+        assert_eq!(board.route_chance(15, 18), 1.0);
+    }
+
+    #[test]
+    fn test_bad_random_routes() {
+        let with_random = |from, chance, to| {
+            Board::new(20, HashMap::from([(15, 5)]))
+                .unwrap()
+                .with_random_routes(HashMap::from([(from, RandomRoute { chance, to })]))
+        };
+        assert_eq!(
+            with_random(5, 0.1, vec![(15, 1.0)]).unwrap_err(),
+            ConfigError::RouteCycle(vec![5, 15])
+        );
+        assert_eq!(
+            with_random(15, 0.5, vec![(3, 1.0)]).unwrap_err(),
+            ConfigError::DuplicateSource(15)
+        );
+        assert_eq!(
+            with_random(4, 0.5, vec![(3, 1.0), (21, 1.0)]).unwrap_err(),
+            ConfigError::OutOfRange {
+                from: 4,
+                to: 21,
+                size: 20
+            }
+        );
+        assert_eq!(
+            with_random(4, 1.5, vec![(3, 1.0)]).unwrap_err().to_string(),
+            "Random route from square 4: chance 1.5 isn't between 0 and 1"
+        );
+        assert!(matches!(
+            with_random(4, 0.5, vec![(3, 0.0)]),
+            Err(ConfigError::BadOdds(4, _))
+        ));
+    }
+
+    #[test]
+    fn test_route_cycle() {
+        let result = Board::new(100, HashMap::from([(4, 25), (25, 4)]));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Snakes and ladders form an endless loop: 4 -> 25 -> 4"
+        );
+    }
+    #[test]
+    fn test_route_cycle_with_tail() {
+        // 2 leads into the loop but isn't part of it
+        let result = Board::new(100, HashMap::from([(2, 10), (10, 30), (30, 20), (20, 10)]));
+        assert_eq!(
+            result.unwrap_err(),
+            ConfigError::RouteCycle(vec![10, 30, 20])
+        );
+    }
+    #[test]
+    fn test_bad_routes() {
+        let result = Board::new(100, HashMap::from([(100, 50)]));
+        assert_eq!(
+            result.unwrap_err(),
+            ConfigError::OutOfRange {
+                from: 100,
+                to: 50,
+                size: 100
+            }
+        );
+        let result = Board::new(100, HashMap::from([(5, 101)]));
+        assert!(matches!(
+            result.unwrap_err(),
+            ConfigError::OutOfRange { to: 101, .. }
+        ));
+        let result = Board::new(100, HashMap::from([(7, 7)]));
+        assert_eq!(result.unwrap_err(), ConfigError::SelfLoop(7));
+    }
+}
@@ -0,0 +1,204 @@
+use snakes_and_ladders::output::Format;
+use snakes_and_ladders::render::Overlay;
+use std::fmt;
+use std::str::FromStr;
+
+pub const USAGE: &str = "Usage: snakes_and_ladders [COMMAND] [OPTIONS]
+
+Commands:
+  simulate       Simulate many games and report stats (default)
+  analyze        Solve the board exactly as a Markov chain
+  validate       Only check the config and board
+  replay <SEED>  Re-run a single game, e.g. one from extreme_seeds
+  render         Draw the board
+  verify         Re-play the dice of a --trace file and check every roll comes out the same
+
+Options:
+  -c, --config <FILE>      Config file [default: config.json]
+  -n, --iterations <N>     Games to simulate, overrides the config file
+      --seed <SEED>        Master seed, overrides the config file
+      --threads <N>        Worker threads [default: one per CPU core]
+  -f, --format <FORMAT>    debug, json, csv or markdown [default: debug]
+  -o, --output <FILE>      Write results to a file instead of stdout
+      --overlay <OVERLAY>  What render marks on each square: none, luck or heatmap [default: none]
+      --svg                Render the board as an SVG picture instead of text
+      --trace <FILE>       JSON Lines log of every roll, written by replay and read by verify
+  -h, --help               Print this help";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Simulate,
+    Analyze,
+    Validate,
+    Replay(u64),
+    Render,
+    Verify,
+    Help,
+}
+
+/// Everything from the command line
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub config: String,
+    pub iterations: Option<usize>,
+    pub seed: Option<u64>,
+    pub threads: Option<usize>,
+    pub format: Format,
+    pub output: Option<String>,
+    pub overlay: Overlay,
+    pub svg: bool,
+    pub trace: Option<String>,
+}
+
+impl Default for Cli {
+    fn default() -> Cli {
+        Cli {
+            command: Command::Simulate,
+            config: "config.json".to_string(),
+            iterations: None,
+            seed: None,
+            threads: None,
+            format: Format::Debug,
+            output: None,
+            overlay: Overlay::None,
+            svg: false,
+            trace: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct UsageError(String);
+impl std::error::Error for UsageError {}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The value after a flag, parsed
+fn value<T: FromStr>(flag: &str, value: Option<&String>) -> Result<T, UsageError>
+where
+    T::Err: fmt::Display,
+{
+    let value = value.ok_or_else(|| UsageError(format!("{} needs a value", flag)))?;
+    value
+        .parse()
+        .map_err(|e| UsageError(format!("Bad value {} for {}: {}", value, flag, e)))
+}
+
+impl Cli {
+    /// args doesn't include the program name
+    pub fn parse(args: &[String]) -> Result<Cli, UsageError> {
+        let mut cli = Cli::default();
+        let mut args = args.iter();
+        let mut command_seen = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "--config" => cli.config = value(arg, args.next())?,
+                "-n" | "--iterations" => cli.iterations = Some(value(arg, args.next())?),
+                "--seed" => cli.seed = Some(value(arg, args.next())?),
+                "--threads" => cli.threads = Some(value(arg, args.next())?),
+                "-f" | "--format" => cli.format = value(arg, args.next())?,
+                "-o" | "--output" => cli.output = Some(value(arg, args.next())?),
+                "--overlay" => cli.overlay = value(arg, args.next())?,
+                "--svg" => cli.svg = true,
+                "--trace" => cli.trace = Some(value(arg, args.next())?),
+                "-h" | "--help" => cli.command = Command::Help,
+                flag if flag.starts_with('-') => {
+                    return Err(UsageError(format!("Unknown option {}", flag)))
+                }
+                command if !command_seen => {
+                    command_seen = true;
+                    let command = match command {
+                        "simulate" => Command::Simulate,
+                        "analyze" => Command::Analyze,
+                        "validate" => Command::Validate,
+                        "replay" => Command::Replay(value(arg, args.next())?),
+                        "render" => Command::Render,
+                        "verify" => Command::Verify,
+                        "help" => Command::Help,
+                        _ => return Err(UsageError(format!("Unknown command {}", command))),
+                    };
+                    if cli.command != Command::Help {
+                        cli.command = command;
+                    }
+                }
+                extra => return Err(UsageError(format!("Unexpected argument {}", extra))),
+            }
+        }
+        if cli.command == Command::Verify && cli.trace.is_none() {
+            return Err(UsageError("verify needs a --trace file".to_string()));
+        }
+        Ok(cli)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, UsageError> {
+        Cli::parse(&args.iter().map(|a| a.to_string()).collect::<Vec<String>>())
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(parse(&[]).unwrap(), Cli::default());
+    }
+
+    #[test]
+    fn test_simulate_options() {
+        let cli = parse(&[
+            "simulate", "-c", "big.json", "-n", "500", "--seed", "7", "-f", "csv", "-o", "out.csv",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli,
+            Cli {
+                command: Command::Simulate,
+                config: "big.json".to_string(),
+                iterations: Some(500),
+                seed: Some(7),
+                threads: None,
+                format: Format::Csv,
+                output: Some("out.csv".to_string()),
+                overlay: Overlay::None,
+                svg: false,
+                trace: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(parse(&["analyze"]).unwrap().command, Command::Analyze);
+        assert_eq!(parse(&["validate"]).unwrap().command, Command::Validate);
+        assert_eq!(
+            parse(&["replay", "42"]).unwrap().command,
+            Command::Replay(42)
+        );
+        assert_eq!(parse(&["analyze", "-h"]).unwrap().command, Command::Help);
+        let cli = parse(&["render", "--overlay", "luck", "--svg"]).unwrap();
+        assert_eq!((cli.command, cli.overlay), (Command::Render, Overlay::Luck));
+        assert!(cli.svg);
+        let cli = parse(&["verify", "--trace", "game.jsonl"]).unwrap();
+        assert_eq!(cli.command, Command::Verify);
+        assert_eq!(cli.trace, Some("game.jsonl".to_string()));
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        assert!(parse(&["--seed"]).is_err());
+        assert!(parse(&["--seed", "abc"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["fly"]).is_err());
+        assert!(parse(&["simulate", "analyze"]).is_err());
+        assert!(parse(&["-f", "xml"]).is_err());
+        assert!(parse(&["replay"]).is_err());
+        assert!(parse(&["render", "--overlay", "colour"]).is_err());
+        assert!(parse(&["verify"]).is_err());
+    }
+}
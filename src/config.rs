@@ -0,0 +1,625 @@
+//! Loading and checking config files that describe a board and its rules.
+
+use crate::boards::{Board, Overshoot, RandomRoute, Special};
+use crate::dice::Die;
+use crate::error::{ConfigError, Problem, RouteList};
+use crate::layout::{all_layouts, layout_count, BoardChange, MAX_LAYOUTS};
+use crate::sim::{RerollLimit, Rules};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+/// The JSON config file, as written
+#[derive(Serialize, Deserialize)]
+pub struct ConfigFile {
+    /// Games to play
+    pub iterations: usize,
+    /// Number of the last square
+    pub size: usize,
+    /// Each snake as [from, to], going down
+    pub snakes: Vec<(usize, usize)>,
+    /// Each ladder as [from, to], going up
+    pub ladders: Vec<(usize, usize)>,
+    /// Snakes and ladders that only sometimes fire, or go to one of several squares
+    #[serde(default)]
+    pub random_routes: Vec<RandomRouteFile>,
+    /// e.g. [12, "lose_turn"]
+    #[serde(default)]
+    pub specials: Vec<(usize, Special)>,
+    /// Abandon games that take longer than this
+    #[serde(default)]
+    pub max_turns: Option<usize>,
+    /// Players per game, 1 if missing
+    #[serde(default = "default_players")]
+    pub players: usize,
+    /// One six-sided die if missing
+    #[serde(default)]
+    pub die: Die,
+    /// What a roll past the last square does
+    #[serde(default)]
+    pub overshoot: Overshoot,
+    /// None to re-roll for as long as the die says so
+    #[serde(default)]
+    pub reroll_limit: Option<RerollLimit>,
+    /// e.g. {"break": {"from": 13, "after": 2}}
+    #[serde(default)]
+    pub board_changes: Vec<BoardChange>,
+    /// Random if missing
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// A random route as written, e.g. {"from": 40, "to": 3, "chance": 0.5}
+#[derive(Serialize, Deserialize)]
+pub struct RandomRouteFile {
+    /// Square the route starts on
+    pub from: usize,
+    /// Where it can go
+    pub to: Destinations,
+    /// Of firing at all, 1 if missing
+    #[serde(default = "default_chance")]
+    pub chance: f64,
+}
+
+/// One square, or several with their weights e.g. [[35, 1], [5, 1], [60, 2]]
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Destinations {
+    /// Always this square
+    One(usize),
+    /// Each square and its weight, relative to the others
+    Weighted(Vec<(usize, f64)>),
+}
+
+impl RandomRouteFile {
+    fn route(&self) -> RandomRoute {
+        let to = match &self.to {
+            Destinations::One(to) => vec![(*to, 1.0)],
+            Destinations::Weighted(to) => to.clone(),
+        };
+        RandomRoute {
+            chance: self.chance,
+            to,
+        }
+    }
+}
+
+fn default_chance() -> f64 {
+    1.0
+}
+
+/// Everything needed to run a batch
+pub struct Config {
+    /// The board the games start on
+    pub board: Board,
+    /// How the games are played
+    pub rules: Rules,
+    /// Games to play
+    pub iterations: usize,
+    /// Master seed for the batch, random if None
+    pub seed: Option<u64>,
+    /// Problems that don't stop the board being played
+    pub warnings: Vec<Problem>,
+}
+
+fn default_players() -> usize {
+    1
+}
+
+/// Read and check a config file, ready to simulate
+pub fn load_cfg(file: &str) -> Result<Config, ConfigError> {
+    let contents = fs::read_to_string(file).map_err(|e| ConfigError::from_io(file, e))?;
+    parse_cfg(&contents)
+}
+
+/// Check the contents of a config file, ready to simulate.
+/// Every problem is reported at once, see ConfigFile::validate
+pub fn parse_cfg(contents: &str) -> Result<Config, ConfigError> {
+    let v: ConfigFile = serde_json::from_str(contents)?;
+    let problems = v.validate();
+    if problems.iter().any(|p| p.is_error()) {
+        return Err(ConfigError::Invalid(problems));
+    }
+    let board = Board::with_specials(v.size, v.routes(), v.specials())?
+        .with_random_routes(v.random_routes())?;
+    let rules = Rules {
+        max_turns: v.max_turns,
+        players: v.players,
+        die: v.die,
+        overshoot: v.overshoot,
+        reroll_limit: v.reroll_limit,
+        board_changes: v.board_changes,
+    };
+    Ok(Config {
+        board,
+        rules,
+        iterations: v.iterations,
+        seed: v.seed,
+        warnings: problems,
+    })
+}
+
+impl ConfigFile {
+    /// Snakes then ladders, with where each one is in the file
+    fn indexed_routes(&self) -> impl Iterator<Item = (RouteList, usize, usize, usize)> + '_ {
+        let snakes = self.snakes.iter().enumerate();
+        let ladders = self.ladders.iter().enumerate();
+        snakes
+            .map(|(i, &(from, to))| (RouteList::Snakes, i, from, to))
+            .chain(ladders.map(|(i, &(from, to))| (RouteList::Ladders, i, from, to)))
+    }
+
+    /// Every snake and ladder, the first one wins if two start on the same square
+    fn routes(&self) -> HashMap<usize, usize> {
+        let mut routes = HashMap::new();
+        for (_, _, from, to) in self.indexed_routes() {
+            routes.entry(from).or_insert(to);
+        }
+        routes
+    }
+
+    /// Every random route, the first one wins if two start on the same square
+    fn random_routes(&self) -> HashMap<usize, RandomRoute> {
+        let mut routes = HashMap::new();
+        for route in self.random_routes.iter() {
+            routes.entry(route.from).or_insert_with(|| route.route());
+        }
+        routes
+    }
+
+    /// Every special square, the first one wins if two are on the same square
+    fn specials(&self) -> HashMap<usize, Special> {
+        let mut specials = HashMap::new();
+        for &(square, special) in self.specials.iter() {
+            specials.entry(square).or_insert(special);
+        }
+        specials
+    }
+
+    /// Everything wrong with the config, not just the first thing.
+    /// The board is only checked for loops and winnability if every route is fine on its own.
+    /// Then so is every layout the board changes lead to, one change at a time then all together
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+        let mut sources: HashSet<usize> = self.routes().into_keys().collect();
+        sources.extend(self.random_routes.iter().map(|r| r.from));
+        let mut seen = HashSet::new();
+        for (list, i, from, to) in self.indexed_routes() {
+            let route = Some((list, i));
+            if list == RouteList::Snakes && from < to {
+                problems.push(Problem::error(route, ConfigError::SnakeGoesUp { from, to }));
+            }
+            if list == RouteList::Ladders && from > to {
+                problems.push(Problem::error(
+                    route,
+                    ConfigError::LadderGoesDown { from, to },
+                ));
+            }
+            if from == 0 || from >= self.size || to > self.size {
+                let size = self.size;
+                problems.push(Problem::error(
+                    route,
+                    ConfigError::OutOfRange { from, to, size },
+                ));
+            }
+            if from == to {
+                problems.push(Problem::error(route, ConfigError::SelfLoop(from)));
+            } else if sources.contains(&to) {
+                // Legal, but probably not what was meant
+                problems.push(Problem::warning(
+                    route,
+                    ConfigError::ChainedRoute { from, to },
+                ));
+            }
+            if !seen.insert(from) {
+                problems.push(Problem::error(route, ConfigError::DuplicateSource(from)));
+            }
+        }
+        for (i, random) in self.random_routes.iter().enumerate() {
+            let route = Some((RouteList::RandomRoutes, i));
+            let from = random.from;
+            let random = random.route();
+            for &(to, _) in random.to.iter() {
+                if from == 0 || from >= self.size || to > self.size {
+                    let size = self.size;
+                    problems.push(Problem::error(
+                        route,
+                        ConfigError::OutOfRange { from, to, size },
+                    ));
+                }
+                if from == to {
+                    problems.push(Problem::error(route, ConfigError::SelfLoop(from)));
+                } else if sources.contains(&to) {
+                    problems.push(Problem::warning(
+                        route,
+                        ConfigError::ChainedRoute { from, to },
+                    ));
+                }
+            }
+            if let Err(e) = random.validate() {
+                problems.push(Problem::error(route, ConfigError::BadOdds(from, e)));
+            }
+            if !seen.insert(from) {
+                problems.push(Problem::error(route, ConfigError::DuplicateSource(from)));
+            }
+        }
+        let mut seen = HashSet::new();
+        for (i, &(square, _)) in self.specials.iter().enumerate() {
+            let special = Some((RouteList::Specials, i));
+            if square == 0 || square >= self.size {
+                let size = self.size;
+                problems.push(Problem::error(
+                    special,
+                    ConfigError::SpecialOffBoard { square, size },
+                ));
+            }
+            if sources.contains(&square) {
+                problems.push(Problem::error(special, ConfigError::SpecialOnRoute(square)));
+            }
+            if !seen.insert(square) {
+                problems.push(Problem::error(
+                    special,
+                    ConfigError::DuplicateSpecial(square),
+                ));
+            }
+        }
+        if self.players == 0 {
+            problems.push(Problem::error(None, ConfigError::NoPlayers));
+        }
+        if let Err(e) = self.die.validate() {
+            problems.push(Problem::error(None, ConfigError::BadDie(e)));
+        }
+        if problems.iter().any(|p| p.is_error()) {
+            return problems;
+        }
+        if let Err(e) = self.check_layout(self.routes()) {
+            problems.push(Problem::error(None, e));
+            return problems;
+        }
+        let routes = self.routes();
+        for (i, change) in self.board_changes.iter().enumerate() {
+            let checked = change.check(&routes).and_then(|_| {
+                all_layouts(&routes, std::slice::from_ref(change))?
+                    .into_iter()
+                    .try_for_each(|layout| self.check_layout(layout))
+            });
+            if let Err(e) = checked {
+                problems.push(Problem::error(Some((RouteList::BoardChanges, i)), e));
+            }
+        }
+        if problems.iter().any(|p| p.is_error()) || self.board_changes.len() < 2 {
+            return problems;
+        }
+        // Changes that are fine on their own could still clash with each other,
+        // but past a point there are too many layouts to go through
+        let count = layout_count(&self.board_changes);
+        if count > MAX_LAYOUTS {
+            let e = ConfigError::BadChange(format!(
+                "too many combined layouts to check together (more than {}), some may be unplayable",
+                MAX_LAYOUTS
+            ));
+            problems.push(Problem::warning(None, e));
+            return problems;
+        }
+        let together = all_layouts(&routes, &self.board_changes).and_then(|layouts| {
+            layouts
+                .into_iter()
+                .try_for_each(|layout| self.check_layout(layout))
+        });
+        if let Err(e) = together {
+            problems.push(Problem::error(None, e));
+        }
+        problems
+    }
+
+    /// Could these snakes and ladders, with everything else in the config, make a playable board?
+    fn check_layout(&self, routes: HashMap<usize, usize>) -> Result<(), ConfigError> {
+        Board::with_specials(self.size, routes, self.specials())?
+            .with_random_routes(self.random_routes())?
+            .check_winnable(&self.die.values(), self.overshoot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid() {
+        let cfg = parse_cfg(
+            r#"{"iterations": 5, "size": 20, "snakes": [[14, 2]], "ladders": [[3, 11]]}"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.board.routes.len(), 2);
+        assert_eq!(cfg.rules.players, 1);
+        assert_eq!(cfg.rules.reroll_limit, None);
+    }
+
+    #[test]
+    fn test_reroll_limit() {
+        let cfg = parse_cfg(
+            r#"{"iterations": 5, "size": 20, "snakes": [], "ladders": [],
+                "reroll_limit": {"max": 2, "penalty": "return_to_start"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.rules.reroll_limit,
+            Some(RerollLimit {
+                max: 2,
+                penalty: crate::sim::Penalty::ReturnToStart
+            })
+        );
+    }
+
+    #[test]
+    fn test_file_not_found() {
+        assert_eq!(
+            load_cfg("no/such/config.json").err(),
+            Some(ConfigError::FileNotFound("no/such/config.json".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_json_syntax() {
+        let result = parse_cfg("{\n  \"iterations\": 5,\n  \"size\": ,\n}");
+        assert!(matches!(
+            result.err(),
+            Some(ConfigError::Json {
+                line: 3,
+                column: 11,
+                ..
+            })
+        ));
+    }
+
+    fn problems(json: &str) -> Vec<Problem> {
+        serde_json::from_str::<ConfigFile>(json).unwrap().validate()
+    }
+
+    #[test]
+    fn test_every_problem() {
+        let problems = problems(
+            r#"{"iterations": 5, "size": 20, "players": 0,
+                "snakes": [[2, 14], [9, 4], [25, 3]], "ladders": [[9, 12], [4, 4], [5, 9]]}"#,
+        );
+        let snake = |i| Some((RouteList::Snakes, i));
+        let ladder = |i| Some((RouteList::Ladders, i));
+        assert_eq!(
+            problems,
+            vec![
+                Problem::error(snake(0), ConfigError::SnakeGoesUp { from: 2, to: 14 }),
+                Problem::warning(snake(1), ConfigError::ChainedRoute { from: 9, to: 4 }),
+                Problem::error(
+                    snake(2),
+                    ConfigError::OutOfRange {
+                        from: 25,
+                        to: 3,
+                        size: 20
+                    }
+                ),
+                Problem::error(ladder(0), ConfigError::DuplicateSource(9)),
+                Problem::error(ladder(1), ConfigError::SelfLoop(4)),
+                Problem::warning(ladder(2), ConfigError::ChainedRoute { from: 5, to: 9 }),
+                Problem::error(None, ConfigError::NoPlayers),
+            ]
+        );
+        assert_eq!(
+            problems[0].to_string(),
+            "error in snakes[0]: Snake from 2 to 14 is going upwards!"
+        );
+    }
+
+    #[test]
+    fn test_specials() {
+        let cfg = parse_cfg(
+            r#"{"iterations": 5, "size": 20, "snakes": [[14, 2]], "ladders": [],
+                "specials": [[5, "lose_turn"], [9, "swap_with_leader"]]}"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.board.specials[&9], Special::SwapWithLeader);
+        let problems = problems(
+            r#"{"iterations": 5, "size": 20, "snakes": [[14, 2]], "ladders": [],
+                "specials": [[14, "extra_roll"], [20, "backwards"], [5, "back_to_start"], [5, "lose_turn"]]}"#,
+        );
+        let special = |i| Some((RouteList::Specials, i));
+        assert_eq!(
+            problems,
+            vec![
+                Problem::error(special(0), ConfigError::SpecialOnRoute(14)),
+                Problem::error(
+                    special(1),
+                    ConfigError::SpecialOffBoard {
+                        square: 20,
+                        size: 20
+                    }
+                ),
+                Problem::error(special(3), ConfigError::DuplicateSpecial(5)),
+            ]
+        );
+        assert_eq!(
+            problems[2].to_string(),
+            "error in specials[3]: Square 5 has more than one special"
+        );
+    }
+
+    #[test]
+    fn test_random_routes() {
+        let cfg = parse_cfg(
+            r#"{"iterations": 5, "size": 20, "snakes": [], "ladders": [],
+                "random_routes": [{"from": 10, "to": 3, "chance": 0.5}, {"from": 12, "to": [[18, 1], [4, 2]]}]}"#,
+        )
+        .unwrap();
+        let random = &cfg.board.random_routes;
+        assert_eq!(
+            (random[&10].chance, random[&10].to.clone()),
+            (0.5, vec![(3, 1.0)])
+        );
+        assert_eq!(
+            (random[&12].chance, random[&12].to.clone()),
+            (1.0, vec![(18, 1.0), (4, 2.0)])
+        );
+        let problems = problems(
+            r#"{"iterations": 5, "size": 20, "snakes": [[14, 2]], "ladders": [],
+                "random_routes": [{"from": 14, "to": 16}, {"from": 5, "to": 8, "chance": 1.5}, {"from": 9, "to": 25}],
+                "specials": [[9, "lose_turn"]]}"#,
+        );
+        let random = |i| Some((RouteList::RandomRoutes, i));
+        assert_eq!(
+            problems,
+            vec![
+                Problem::error(random(0), ConfigError::DuplicateSource(14)),
+                Problem::error(
+                    random(1),
+                    ConfigError::BadOdds(5, "chance 1.5 isn't between 0 and 1".to_string())
+                ),
+                Problem::error(
+                    random(2),
+                    ConfigError::OutOfRange {
+                        from: 9,
+                        to: 25,
+                        size: 20
+                    }
+                ),
+                Problem::error(
+                    Some((RouteList::Specials, 0)),
+                    ConfigError::SpecialOnRoute(9)
+                ),
+            ]
+        );
+        assert_eq!(
+            problems[1].to_string(),
+            "error in random_routes[1]: Random route from square 5: chance 1.5 isn't between 0 and 1"
+        );
+    }
+
+    #[test]
+    fn test_board_changes() {
+        let cfg = parse_cfg(
+            r#"{"iterations": 5, "size": 20, "snakes": [[14, 2]], "ladders": [[3, 8]],
+                "board_changes": [{"break": {"from": 3, "after": 2}},
+                    {"move": {"from": 14, "every": 5, "positions": [[15, 1]]}},
+                    {"rotate": {"every": 10, "layouts": [[[6, 12]], []]}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.rules.board_changes.len(), 3);
+        assert_eq!(
+            cfg.rules.board_changes[0],
+            BoardChange::Break { from: 3, after: 2 }
+        );
+        let problems = problems(
+            r#"{"iterations": 5, "size": 20, "snakes": [[14, 2]], "ladders": [[3, 8]],
+                "board_changes": [{"break": {"from": 4, "after": 2}},
+                    {"move": {"from": 14, "every": 5, "positions": [[3, 1]]}},
+                    {"rotate": {"every": 10, "layouts": [[[6, 12], [12, 6]]]}},
+                    {"move": {"from": 14, "every": 0, "positions": [[15, 1]]}}]}"#,
+        );
+        let change = |i| Some((RouteList::BoardChanges, i));
+        assert_eq!(
+            problems,
+            vec![
+                Problem::error(change(0), ConfigError::NoRoute(4)),
+                Problem::error(change(1), ConfigError::DuplicateSource(3)),
+                Problem::error(change(2), ConfigError::RouteCycle(vec![6, 12])),
+                Problem::error(
+                    change(3),
+                    ConfigError::BadChange("every must be at least 1".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            problems[0].to_string(),
+            "error in board_changes[0]: No snake or ladder starts on square 4"
+        );
+    }
+
+    #[test]
+    fn test_board_changes_together() {
+        // Either move is fine, but together they make a loop
+        let looped = problems(
+            r#"{"iterations": 5, "size": 50, "snakes": [[15, 5]], "ladders": [[20, 30]],
+                "board_changes": [{"move": {"from": 15, "every": 1, "positions": [[10, 25]]}},
+                    {"move": {"from": 20, "every": 1, "positions": [[25, 10]]}}]}"#,
+        );
+        assert_eq!(
+            looped,
+            vec![Problem::error(None, ConfigError::RouteCycle(vec![10, 25]))]
+        );
+        // Breaking either ladder leaves a way past the snake, but not both
+        let both_broken = problems(
+            r#"{"iterations": 5, "size": 20, "snakes": [[12, 1]], "ladders": [[5, 15], [8, 14]],
+                "die": {"faces": [1], "reroll_on": "never"},
+                "board_changes": [{"break": {"from": 5, "after": 1}}, {"break": {"from": 8, "after": 1}}]}"#,
+        );
+        assert!(matches!(
+            both_broken[..],
+            [Problem {
+                route: None,
+                error: ConfigError::Unwinnable(_),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_board_changes_too_many() {
+        // Breaking any of 11 ladders gives 2048 layouts, only checked one change at a time
+        let ladders: Vec<String> = (0..11)
+            .map(|i| format!("[{}, {}]", 3 * i + 2, 3 * i + 3))
+            .collect();
+        let breaks: Vec<String> = (0..11)
+            .map(|i| format!(r#"{{"break": {{"from": {}, "after": 1}}}}"#, 3 * i + 2))
+            .collect();
+        let cfg = parse_cfg(&format!(
+            r#"{{"iterations": 5, "size": 50, "snakes": [], "ladders": [{}], "board_changes": [{}]}}"#,
+            ladders.join(", "),
+            breaks.join(", ")
+        ))
+        .unwrap();
+        assert_eq!(cfg.warnings.len(), 1);
+        assert_eq!(
+            cfg.warnings[0].to_string(),
+            format!(
+                "warning: Bad board change: too many combined layouts to check together (more than {}), some may be unplayable",
+                MAX_LAYOUTS
+            )
+        );
+    }
+
+    #[test]
+    fn test_board_problems() {
+        // Only checked once every route is fine on its own
+        let result = parse_cfg(
+            r#"{"iterations": 5, "size": 20, "snakes": [[12, 4]], "ladders": [[4, 12]]}"#,
+        );
+        let cycle = ConfigError::RouteCycle(vec![4, 12]);
+        assert_eq!(
+            result.err(),
+            Some(ConfigError::Invalid(vec![
+                Problem::warning(
+                    Some((RouteList::Snakes, 0)),
+                    ConfigError::ChainedRoute { from: 12, to: 4 }
+                ),
+                Problem::warning(
+                    Some((RouteList::Ladders, 0)),
+                    ConfigError::ChainedRoute { from: 4, to: 12 }
+                ),
+                Problem::error(None, cycle),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_warnings_only() {
+        let cfg = parse_cfg(
+            r#"{"iterations": 5, "size": 20, "snakes": [[14, 2]], "ladders": [[3, 14]]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.warnings,
+            vec![Problem::warning(
+                Some((RouteList::Ladders, 0)),
+                ConfigError::ChainedRoute { from: 3, to: 14 }
+            )]
+        );
+    }
+}
@@ -1,34 +1,340 @@
-use rand::rngs::ThreadRng;
+//! Dice, how they roll, and the rolls a player can get from them.
+
+use rand::distributions::{Distribution, WeightedIndex};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Default number of faces
+pub const DIE_SIZE: usize = 6;
 
-pub const DIE_SIZE: usize = 6; // Must be >= 1
+/// Seed for the index-th game (or player) from a master seed, so any one of them can be re-run on its own
+pub fn derive_seed(seed: u64, index: u64) -> u64 {
+    // SplitMix64, neighbouring indices give unrelated seeds
+    let mut z = seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
 
-pub trait Roll {
-    // Either a random die or a mock.
+/// Where a Sim gets its dice from, one die per call, and any other chances it takes
+pub trait Roll: Send {
+    /// The value on one die. Send so games can run on worker threads
     fn roll(&mut self) -> usize;
+    /// Index of one of the weights, picked with a chance in proportion to it e.g. where a random route goes
+    fn choose(&mut self, weights: &[f64]) -> usize;
+}
+
+/// Which dice earn another roll in the same turn
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RerollOn {
+    /// No roll earns another
+    Never,
+    /// Every die shows its highest face
+    #[default]
+    MaxFace,
+    /// Every die shows this face
+    Face(usize),
+    /// At least two dice show the same face
+    AnyDouble,
+}
+
+/// Either a normal die numbered 1 to n, or a die with any values on it e.g. [1, 1, 2, 3, 5, 8]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Faces {
+    /// Numbered 1 to this
+    Count(usize),
+    /// A 0 is a blank face
+    Values(Vec<usize>),
+}
+
+/// The shape of the die and how many are rolled at once, as set in the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Die {
+    /// Numbered 1 to 6 if missing
+    #[serde(default = "default_faces")]
+    pub faces: Faces,
+    /// Dice per roll, the roll moves by their total
+    #[serde(default = "default_count")]
+    pub count: usize,
+    /// Relative chance of each face, a fair die if missing
+    #[serde(default)]
+    pub weights: Option<Vec<f64>>,
+    /// Which dice earn another roll
+    #[serde(default)]
+    pub reroll_on: RerollOn,
+}
+
+fn default_faces() -> Faces {
+    Faces::Count(DIE_SIZE)
+}
+
+fn default_count() -> usize {
+    1
+}
+
+impl Default for Die {
+    fn default() -> Die {
+        Die {
+            faces: default_faces(),
+            count: default_count(),
+            weights: None,
+            reroll_on: RerollOn::MaxFace,
+        }
+    }
 }
 
-impl Roll for ThreadRng {
+impl Die {
+    /// A fair die numbered 1 to faces
+    pub fn numbered(faces: usize) -> Die {
+        Die {
+            faces: Faces::Count(faces),
+            ..Die::default()
+        }
+    }
+
+    /// Explains what's wrong with a die from the config file
+    pub fn validate(&self) -> Result<(), String> {
+        let faces = self.faces();
+        if faces.is_empty() {
+            return Err("The die needs at least one face".to_string());
+        }
+        if self.count == 0 {
+            return Err("Need to roll at least one die".to_string());
+        }
+        if let Some(weights) = &self.weights {
+            if weights.len() != faces.len() {
+                return Err(format!(
+                    "The die has {} faces but {} weights",
+                    faces.len(),
+                    weights.len()
+                ));
+            }
+            WeightedIndex::new(weights).map_err(|e| format!("Bad die weights: {}", e))?;
+        }
+        if let RerollOn::Face(face) = self.reroll_on {
+            if !faces.contains(&face) {
+                return Err(format!(
+                    "Can't re-roll on {}, the die has no such face",
+                    face
+                ));
+            }
+        }
+        if self.reroll_on == RerollOn::AnyDouble && self.count < 2 {
+            return Err("Can't re-roll on doubles with a single die".to_string());
+        }
+        Ok(())
+    }
+
+    /// The value on each face of the die, in order
+    pub fn faces(&self) -> Vec<usize> {
+        match &self.faces {
+            Faces::Count(n) => (1..=*n).collect(),
+            Faces::Values(values) => values.clone(),
+        }
+    }
+
+    /// The highest value on the die
+    pub fn max_face(&self) -> usize {
+        match &self.faces {
+            Faces::Count(n) => *n,
+            Faces::Values(values) => values.iter().copied().max().unwrap_or(0),
+        }
+    }
+
+    /// Each face's value and the chance of rolling it, for a single die
+    pub fn outcomes(&self) -> Vec<(usize, f64)> {
+        let faces = self.faces();
+        let weights = match &self.weights {
+            Some(weights) => weights.clone(),
+            None => vec![1.0; faces.len()],
+        };
+        let total: f64 = weights.iter().sum();
+        faces
+            .into_iter()
+            .zip(weights)
+            .map(|(v, w)| (v, w / total))
+            .collect()
+    }
+
+    /// Every total the dice can roll, the chance of it and whether it earns a re-roll, in order.
+    /// Adds one die at a time, remembering only what decides the re-roll, so it's fine with lots of dice
+    pub fn roll_outcomes(&self) -> Vec<(usize, f64, bool)> {
+        let single: Vec<(usize, f64)> = self
+            .outcomes()
+            .into_iter()
+            .filter(|&(_, p)| p > 0.0)
+            .collect();
+        let check = self.reroll_check();
+        // (total, rerolls so far, faces seen so far), the faces are only kept for AnyDouble
+        // until there's a double
+        let start = (0, matches!(check, RerollCheck::Every(_)), vec![]);
+        let mut rolls: BTreeMap<(usize, bool, Vec<usize>), f64> = BTreeMap::from([(start, 1.0)]);
+        for _ in 0..self.count {
+            let mut next = BTreeMap::new();
+            for ((total, rerolls, seen), p) in rolls {
+                for &(v, q) in single.iter() {
+                    let key = match check {
+                        RerollCheck::Never => (total + v, false, vec![]),
+                        RerollCheck::Every(face) => (total + v, rerolls && v == face, vec![]),
+                        RerollCheck::AnyDouble if rerolls || seen.contains(&v) => {
+                            (total + v, true, vec![])
+                        }
+                        RerollCheck::AnyDouble => {
+                            let mut seen = seen.clone();
+                            seen.push(v);
+                            seen.sort_unstable();
+                            (total + v, false, seen)
+                        }
+                    };
+                    *next.entry(key).or_insert(0.0) += p * q;
+                }
+            }
+            rolls = next;
+        }
+        let mut totals: BTreeMap<(usize, bool), f64> = BTreeMap::new();
+        for ((total, rerolls, _), p) in rolls {
+            *totals.entry((total, rerolls)).or_insert(0.0) += p;
+        }
+        totals
+            .into_iter()
+            .map(|((total, rerolls), p)| (total, p, rerolls))
+            .collect()
+    }
+
+    /// Every total the dice can actually roll
+    pub fn values(&self) -> Vec<usize> {
+        let mut values: Vec<usize> = self
+            .roll_outcomes()
+            .into_iter()
+            .map(|(total, _, _)| total)
+            .collect();
+        values.dedup();
+        values
+    }
+
+    /// Does rolling these dice earn another roll? See reroll_check to ask over and over
+    pub fn rerolls(&self, dice: &[usize]) -> bool {
+        self.reroll_check().rerolls(dice)
+    }
+
+    /// What rerolls has to check, worked out once for the die
+    pub fn reroll_check(&self) -> RerollCheck {
+        match self.reroll_on {
+            RerollOn::Never => RerollCheck::Never,
+            RerollOn::MaxFace => RerollCheck::Every(self.max_face()),
+            RerollOn::Face(face) => RerollCheck::Every(face),
+            RerollOn::AnyDouble => RerollCheck::AnyDouble,
+        }
+    }
+
+    /// A random source of rolls for this die. Assumes the die is valid
+    pub fn roller<R: Rng + Send + 'static>(&self, rng: R) -> Box<dyn Roll> {
+        if let (Faces::Count(faces), None) = (&self.faces, &self.weights) {
+            return Box::new(FairDie { faces: *faces, rng });
+        }
+        let weights = self.outcomes().into_iter().map(|(_, p)| p);
+        Box::new(WeightedDie {
+            faces: self.faces(),
+            weights: WeightedIndex::new(weights).unwrap(),
+            rng,
+        })
+    }
+}
+
+/// Which dice earn another roll, see Die::reroll_check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RerollCheck {
+    /// No roll earns another
+    Never,
+    /// Every die shows this face
+    Every(usize),
+    /// At least two dice show the same face
+    AnyDouble,
+}
+
+impl RerollCheck {
+    /// True if these dice earn another roll
+    pub fn rerolls(self, dice: &[usize]) -> bool {
+        match self {
+            RerollCheck::Never => false,
+            RerollCheck::Every(face) => dice.iter().all(|&v| v == face),
+            RerollCheck::AnyDouble => dice
+                .iter()
+                .enumerate()
+                .any(|(i, v)| dice[i + 1..].contains(v)),
+        }
+    }
+}
+
+/// Numbered 1 to faces, each equally likely
+pub struct FairDie<R: Rng> {
+    /// Number of faces
+    pub faces: usize,
+    /// Where the randomness comes from
+    pub rng: R,
+}
+
+impl<R: Rng + Send> Roll for FairDie<R> {
     fn roll(&mut self) -> usize {
-        self.gen_range(1, DIE_SIZE + 1)
+        self.rng.gen_range(1, self.faces + 1)
+    }
+
+    fn choose(&mut self, weights: &[f64]) -> usize {
+        WeightedIndex::new(weights).unwrap().sample(&mut self.rng)
+    }
+}
+
+/// Any values on the faces, each with its own chance of coming up
+pub struct WeightedDie<R: Rng> {
+    /// The value on each face
+    pub faces: Vec<usize>,
+    /// Chance of each face, in the same order
+    pub weights: WeightedIndex<f64>,
+    /// Where the randomness comes from
+    pub rng: R,
+}
+
+impl<R: Rng + Send> Roll for WeightedDie<R> {
+    fn roll(&mut self) -> usize {
+        self.faces[self.weights.sample(&mut self.rng)]
+    }
+
+    fn choose(&mut self, weights: &[f64]) -> usize {
+        WeightedIndex::new(weights).unwrap().sample(&mut self.rng)
     }
 }
 
-pub struct Unrollable {} // Fallback class, used for testing only
+/// Panics if rolled, for tests that never roll
+#[cfg(test)]
+pub struct Unrollable {}
 
+#[cfg(test)]
 impl Roll for Unrollable {
     fn roll(&mut self) -> usize {
         panic!("Can't roll this!")
     }
+
+    fn choose(&mut self, _weights: &[f64]) -> usize {
+        panic!("Can't choose with this!")
+    }
 }
 
+/// Gives some predetermined results, then panics. Used for testing and replaying traces
+#[doc(hidden)]
 pub struct MockDie {
-    // gives some predetermined results, then panics. Used for testing only
-    pub queued_results: Vec<usize>, // Popped RIGHT to LEFT!!
+    /// Popped RIGHT to LEFT!! Choices are queued as the index to pick
+    pub queued_results: Vec<usize>,
 }
 
 impl Roll for MockDie {
     fn roll(&mut self) -> usize {
         self.queued_results.pop().unwrap()
     }
+
+    fn choose(&mut self, _weights: &[f64]) -> usize {
+        self.queued_results.pop().unwrap()
+    }
 }
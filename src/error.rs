@@ -0,0 +1,268 @@
+//! The errors a config can have, and where in the file they came from.
+
+use std::{fmt, io};
+
+/// Everything that can stop a config file turning into a playable board
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// The config file's path
+    FileNotFound(String),
+    /// File and what went wrong reading it
+    Io(String, String),
+    /// The file isn't valid JSON, or doesn't fit the config's shape
+    Json {
+        /// As serde_json counts them, from 1
+        line: usize,
+        /// As serde_json counts them, from 1
+        column: usize,
+        /// What serde_json says is wrong
+        message: String,
+    },
+    /// A snake that ends higher than it starts
+    SnakeGoesUp {
+        /// Top of the snake
+        from: usize,
+        /// Bottom of the snake
+        to: usize,
+    },
+    /// A ladder that ends lower than it starts
+    LadderGoesDown {
+        /// Bottom of the ladder
+        from: usize,
+        /// Top of the ladder
+        to: usize,
+    },
+    /// Two snakes or ladders start on this square
+    DuplicateSource(usize),
+    /// A snake or ladder with an end off the board
+    OutOfRange {
+        /// Where it starts
+        from: usize,
+        /// Where it ends
+        to: usize,
+        /// Size of the board
+        size: usize,
+    },
+    /// A snake or ladder that starts and ends on this square
+    SelfLoop(usize),
+    /// Squares in the loop, starting from the lowest
+    RouteCycle(Vec<usize>),
+    /// The lowest square that can never get to the finish
+    Unwinnable(usize),
+    /// A game needs at least one player
+    NoPlayers,
+    /// What's wrong with the die
+    BadDie(String),
+    /// A snake or ladder that ends where another starts
+    ChainedRoute {
+        /// Where it starts
+        from: usize,
+        /// Also the start of another snake or ladder
+        to: usize,
+    },
+    /// A special square that isn't on the board
+    SpecialOffBoard {
+        /// The special square
+        square: usize,
+        /// Size of the board
+        size: usize,
+    },
+    /// A special square where a snake or ladder starts
+    SpecialOnRoute(usize),
+    /// Two specials on this square
+    DuplicateSpecial(usize),
+    /// A random route's start, and what's wrong with its chances
+    BadOdds(usize, String),
+    /// A board change is about a snake or ladder that isn't there
+    NoRoute(usize),
+    /// What's wrong with a board change
+    BadChange(String),
+    /// Everything wrong with a config file, see ConfigFile::validate
+    Invalid(Vec<Problem>),
+}
+impl std::error::Error for ConfigError {}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::FileNotFound(file) => write!(f, "Config file {} not found", file),
+            ConfigError::Io(file, e) => write!(f, "Can't read config file {}: {}", file, e),
+            ConfigError::Json {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Bad config at line {} column {}: {}",
+                line, column, message
+            ),
+            ConfigError::SnakeGoesUp { from, to } => {
+                write!(f, "Snake from {} to {} is going upwards!", from, to)
+            }
+            ConfigError::LadderGoesDown { from, to } => {
+                write!(f, "Ladder from {} to {} is going downwards!", from, to)
+            }
+            ConfigError::DuplicateSource(square) => {
+                write!(f, "Duplicate snake or ladder from square {}", square)
+            }
+            ConfigError::OutOfRange { from, to, size } => write!(
+                f,
+                "Snake or ladder from {} to {} doesn't fit on a board of {} squares",
+                from, to, size
+            ),
+            ConfigError::SelfLoop(square) => {
+                write!(f, "Snake or ladder links to itself on square {}", square)
+            }
+            ConfigError::RouteCycle(cycle) => {
+                let squares: Vec<String> = cycle.iter().map(|s| s.to_string()).collect();
+                write!(
+                    f,
+                    "Snakes and ladders form an endless loop: {} -> {}",
+                    squares.join(" -> "),
+                    cycle[0]
+                )
+            }
+            ConfigError::Unwinnable(square) => {
+                write!(f, "Board can't be won after reaching square {}", square)
+            }
+            ConfigError::NoPlayers => write!(f, "Need at least one player"),
+            ConfigError::BadDie(e) => write!(f, "{}", e),
+            ConfigError::ChainedRoute { from, to } => write!(
+                f,
+                "Snake or ladder from {} to {} lands on another snake or ladder",
+                from, to
+            ),
+            ConfigError::SpecialOffBoard { square, size } => write!(
+                f,
+                "Special square {} isn't between the start and the finish of a board of {} squares",
+                square, size
+            ),
+            ConfigError::SpecialOnRoute(square) => write!(
+                f,
+                "Special square {} is also the start of a snake or ladder",
+                square
+            ),
+            ConfigError::DuplicateSpecial(square) => {
+                write!(f, "Square {} has more than one special", square)
+            }
+            ConfigError::BadOdds(square, e) => {
+                write!(f, "Random route from square {}: {}", square, e)
+            }
+            ConfigError::NoRoute(square) => {
+                write!(f, "No snake or ladder starts on square {}", square)
+            }
+            ConfigError::BadChange(e) => write!(f, "Bad board change: {}", e),
+            ConfigError::Invalid(problems) => {
+                let errors = problems.iter().filter(|p| p.is_error()).count();
+                write!(f, "Config has {} error(s)", errors)?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Warnings don't stop the board being played, errors do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The board can still be played
+    Warning,
+    /// The board can't be played
+    Error,
+}
+
+/// Which list in the config file a route or special square came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RouteList {
+    /// snakes
+    Snakes,
+    /// ladders
+    Ladders,
+    /// specials
+    Specials,
+    /// random_routes
+    RandomRoutes,
+    /// board_changes
+    BoardChanges,
+}
+
+/// One thing wrong with a config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// Whether it stops the board being played
+    pub severity: Severity,
+    /// Index into its list in the config file, None if it's not about one
+    pub route: Option<(RouteList, usize)>,
+    /// What's wrong
+    pub error: ConfigError,
+}
+
+impl Problem {
+    /// A problem that stops the board being played
+    pub fn error(route: Option<(RouteList, usize)>, error: ConfigError) -> Problem {
+        Problem {
+            severity: Severity::Error,
+            route,
+            error,
+        }
+    }
+
+    /// A problem that doesn't stop the board being played
+    pub fn warning(route: Option<(RouteList, usize)>, error: ConfigError) -> Problem {
+        Problem {
+            severity: Severity::Warning,
+            route,
+            error,
+        }
+    }
+
+    /// True if it stops the board being played
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning")?,
+            Severity::Error => write!(f, "error")?,
+        }
+        match self.route {
+            Some((RouteList::Snakes, i)) => write!(f, " in snakes[{}]", i)?,
+            Some((RouteList::Ladders, i)) => write!(f, " in ladders[{}]", i)?,
+            Some((RouteList::Specials, i)) => write!(f, " in specials[{}]", i)?,
+            Some((RouteList::RandomRoutes, i)) => write!(f, " in random_routes[{}]", i)?,
+            Some((RouteList::BoardChanges, i)) => write!(f, " in board_changes[{}]", i)?,
+            None => {}
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+impl ConfigError {
+    pub(crate) fn from_io(file: &str, e: io::Error) -> ConfigError {
+        match e.kind() {
+            io::ErrorKind::NotFound => ConfigError::FileNotFound(file.to_string()),
+            _ => ConfigError::Io(file.to_string(), e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> ConfigError {
+        // serde_json's own message ends with the position, which is in its own fields here
+        let message = e.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(i) => message[..i].to_string(),
+            None => message,
+        };
+        ConfigError::Json {
+            line: e.line(),
+            column: e.column(),
+            message,
+        }
+    }
+}
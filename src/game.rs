@@ -0,0 +1,242 @@
+//! A game of one or more players taking turns on the same board.
+
+use crate::layout::BoardState;
+use crate::sim::Sim;
+use std::cmp::Reverse;
+
+/// Several players racing on the same board, taking turns in seat order
+pub struct Game {
+    /// Seat 0 goes first
+    pub players: Vec<Sim>,
+    /// Seat of the player who won, None until somebody does
+    pub winner: Option<usize>,
+    board: Option<BoardState>, // Shared by every player, see take_turn. None without players
+}
+
+impl Game {
+    /// A game that hasn't started, players in seat order. Everyone plays on seat 0's board
+    pub fn new(players: Vec<Sim>) -> Game {
+        let board = players
+            .first()
+            .map(|p| p.board_state().clone().for_players(players.len()));
+        Game {
+            players,
+            winner: None,
+            board,
+        }
+    }
+
+    /// Take turns until somebody wins, or until somebody runs out of turns.
+    /// A game with no players is over before it starts
+    pub fn run(&mut self) {
+        self.play();
+        self.share_board();
+    }
+
+    fn play(&mut self) {
+        while self.winner.is_none() && !self.players.is_empty() {
+            for seat in 0..self.players.len() {
+                if self.players[seat].out_of_turns() {
+                    // Nobody wins, give up on the whole game
+                    for player in self.players.iter_mut() {
+                        player.abandoned = true;
+                    }
+                    return;
+                }
+                self.take_turn(seat, Sim::turn);
+                if self.players[seat].has_won() {
+                    self.winner = Some(seat);
+                    return;
+                }
+                if self.players[seat].take_swap() {
+                    swap_with_leader(&mut self.players, seat);
+                }
+            }
+        }
+    }
+
+    /// Lend the seat the shared board while it plays, so its changes are everyone's
+    pub(crate) fn take_turn<T>(&mut self, seat: usize, play: impl FnOnce(&mut Sim) -> T) -> T {
+        let player = &mut self.players[seat];
+        let Some(board) = &mut self.board else {
+            return play(player);
+        };
+        player.swap_board(board);
+        let result = play(player);
+        player.swap_board(board);
+        result
+    }
+
+    /// Leave every player with the board as the game ended, see Sim::board
+    pub(crate) fn share_board(&mut self) {
+        if let Some(board) = &self.board {
+            for player in self.players.iter_mut() {
+                player.swap_board(&mut board.clone());
+            }
+        }
+    }
+
+    /// True if the game ran out of turns before anybody won
+    pub fn abandoned(&self) -> bool {
+        self.players.iter().any(|p| p.abandoned)
+    }
+}
+
+/// Swap the seat's square with whoever is furthest ahead, if they're ahead of it.
+/// Ties go to the earlier seat, see Special::SwapWithLeader
+pub fn swap_with_leader(players: &mut [Sim], seat: usize) {
+    let leader = (0..players.len())
+        .filter(|&other| other != seat)
+        .max_by_key(|&other| (players[other].position(), Reverse(other)));
+    let Some(leader) = leader else {
+        return;
+    };
+    if players[leader].position() <= players[seat].position() {
+        return;
+    }
+    let (low, high) = players.split_at_mut(seat.max(leader));
+    let (earlier, later) = (&mut low[seat.min(leader)], &mut high[0]);
+    if seat < leader {
+        earlier.swap_with(later);
+    } else {
+        later.swap_with(earlier);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boards::{blank, Board, Special};
+    use crate::dice::MockDie;
+    use crate::layout::BoardChange;
+    use crate::sim::Rules;
+    use std::collections::HashMap;
+
+    fn player(rules: &Rules, results: Vec<usize>) -> Sim {
+        Sim::with_rules(
+            blank(10),
+            rules.clone(),
+            Box::new(MockDie {
+                queued_results: results,
+            }),
+        )
+    }
+
+    #[test]
+    fn test_first_to_finish_wins() {
+        let rules = Rules::default();
+        // Seat 0 needs three turns, seat 1 needs two
+        let mut game = Game::new(vec![
+            player(&rules, vec![4, 3, 3]),
+            player(&rules, vec![5, 5]),
+        ]);
+        game.run();
+        assert_eq!(game.winner, Some(1));
+        assert_eq!(game.players[0].turn_count, 2);
+        assert_eq!(game.players[1].turn_count, 2);
+        assert!(!game.abandoned());
+    }
+
+    #[test]
+    fn test_earlier_seat_wins_ties() {
+        let rules = Rules::default();
+        let mut game = Game::new(vec![player(&rules, vec![5, 5]), player(&rules, vec![5, 5])]);
+        game.run();
+        assert_eq!(game.winner, Some(0));
+        assert_eq!(game.players[1].turn_count, 1);
+    }
+
+    #[test]
+    fn test_swap_with_leader() {
+        let board = Board::with_specials(
+            10,
+            HashMap::new(),
+            HashMap::from([(2, Special::SwapWithLeader)]),
+        )
+        .unwrap();
+        let player = |results| {
+            Sim::with_rules(
+                board.clone(),
+                Rules::default(),
+                Box::new(MockDie {
+                    queued_results: results,
+                }),
+            )
+        };
+        // Seats 0 and 1 both reach 5, seat 2 lands on the swap
+        let mut players = vec![player(vec![1, 5]), player(vec![5]), player(vec![2])];
+        for sim in players.iter_mut() {
+            sim.turn();
+        }
+        assert!(players[2].take_swap());
+        swap_with_leader(&mut players, 2);
+        let squares: Vec<usize> = players.iter().map(|p| p.position()).collect();
+        assert_eq!(squares, vec![2, 5, 5]);
+        assert_eq!(players[2].swaps, 1);
+        // Already in the lead, nothing happens
+        swap_with_leader(&mut players, 1);
+        assert_eq!(players[1].swaps, 0);
+    }
+
+    #[test]
+    fn test_lose_turn() {
+        let board =
+            Board::with_specials(7, HashMap::new(), HashMap::from([(3, Special::LoseTurn)]))
+                .unwrap();
+        let rng = Box::new(MockDie {
+            queued_results: vec![4, 3],
+        });
+        let mut game = Game::new(vec![Sim::with_rules(board, Rules::default(), rng)]);
+        game.run();
+        assert_eq!(game.winner, Some(0));
+        assert_eq!(game.players[0].turn_count, 3);
+        assert_eq!(game.players[0].turns_lost, 1);
+    }
+
+    #[test]
+    fn test_abandoned() {
+        let rules = Rules {
+            max_turns: Some(2),
+            ..Rules::default()
+        };
+        let mut game = Game::new(vec![player(&rules, vec![1, 1]), player(&rules, vec![1, 1])]);
+        game.run();
+        assert_eq!(game.winner, None);
+        assert!(game.abandoned());
+        assert!(game.players.iter().all(|p| p.abandoned));
+    }
+
+    #[test]
+    fn test_shared_board() {
+        let rules = Rules {
+            board_changes: vec![BoardChange::Break { from: 3, after: 2 }],
+            ..Rules::default()
+        };
+        let board = Board::new(10, HashMap::from([(3, 8)])).unwrap();
+        let player = |results| {
+            Sim::with_rules(
+                board.clone(),
+                rules.clone(),
+                Box::new(MockDie {
+                    queued_results: results,
+                }),
+            )
+        };
+        // Seats 0 and 1 climb the ladder once each, which breaks it for seat 2 too
+        let mut game = Game::new(vec![player(vec![2, 3]), player(vec![3]), player(vec![3])]);
+        game.run();
+        assert_eq!(game.winner, Some(0));
+        let seat_2 = &game.players[2];
+        assert_eq!((seat_2.position(), seat_2.climb_count), (3, 0));
+        // Seat 2 sees the square isn't lucky any more
+        assert_eq!(seat_2.lucky_rolls, 0);
+        assert!(game.players.iter().all(|p| p.board().routes.is_empty()));
+    }
+
+    #[test]
+    fn test_no_players() {
+        let mut game = Game::new(vec![]);
+        game.run();
+        assert_eq!(game.winner, None);
+    }
+}
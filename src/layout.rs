@@ -0,0 +1,393 @@
+//! Snakes and ladders that move, break or rotate as a game goes on.
+
+use crate::boards::Board;
+use crate::error::ConfigError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Past this many, the layouts changes lead to together are too many to check one by one
+pub const MAX_LAYOUTS: usize = 1024;
+
+/// A way the snakes and ladders change during a game, see Rules::board_changes.
+/// Every player plays on the same board, and turns are counted in rounds of one turn each
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoardChange {
+    /// Every few turns, the snake or ladder starting on from moves to the next of positions,
+    /// then back to where it started
+    Move {
+        /// Where the snake or ladder starts on the board
+        from: usize,
+        /// Turns between moves
+        every: usize,
+        /// Each (from, to) it moves to in turn
+        positions: Vec<(usize, usize)>,
+    },
+    /// The snake or ladder starting on from is gone once it's been taken after times
+    Break {
+        /// Where the snake or ladder starts
+        from: usize,
+        /// Times it can be taken
+        after: usize,
+    },
+    /// Every few turns, all the snakes and ladders are swapped for the next layout,
+    /// then back to the board's own
+    Rotate {
+        /// Turns between swaps
+        every: usize,
+        /// Each layout's (from, to) snakes and ladders
+        layouts: Vec<Vec<(usize, usize)>>,
+    },
+}
+
+impl BoardChange {
+    /// Checks the change makes sense for a board starting with these routes, on its own
+    pub fn check(&self, routes: &HashMap<usize, usize>) -> Result<(), ConfigError> {
+        match self {
+            BoardChange::Move {
+                from,
+                every,
+                positions,
+            } => {
+                check_at_least_1("every", *every)?;
+                if positions.is_empty() {
+                    return Err(ConfigError::BadChange("nowhere to move to".to_string()));
+                }
+                if !routes.contains_key(from) {
+                    return Err(ConfigError::NoRoute(*from));
+                }
+            }
+            BoardChange::Break { from, after } => {
+                check_at_least_1("after", *after)?;
+                if !routes.contains_key(from) {
+                    return Err(ConfigError::NoRoute(*from));
+                }
+            }
+            BoardChange::Rotate { every, layouts } => {
+                check_at_least_1("every", *every)?;
+                if layouts.is_empty() {
+                    return Err(ConfigError::BadChange(
+                        "no layouts to rotate to".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The states of the change that give different layouts, see combine
+    fn states(&self) -> Vec<usize> {
+        match self {
+            BoardChange::Move { positions, .. } => (0..=positions.len()).collect(),
+            BoardChange::Break { after, .. } => vec![0, *after],
+            BoardChange::Rotate { layouts, .. } => (0..=layouts.len()).collect(),
+        }
+    }
+}
+
+fn check_at_least_1(name: &str, value: usize) -> Result<(), ConfigError> {
+    if value == 0 {
+        return Err(ConfigError::BadChange(format!(
+            "{} must be at least 1",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// The snakes and ladders once every change is in the given state: how many times a Break's route
+/// has been taken, or which of its positions or layouts a Move or Rotate is on, 0 for none.
+/// A rotation picks the layout, the last one wins, then routes are moved and broken from there
+pub fn combine(
+    routes: &HashMap<usize, usize>,
+    changes: &[BoardChange],
+    states: &[usize],
+) -> Result<HashMap<usize, usize>, ConfigError> {
+    let mut layout = routes.clone();
+    for (change, &state) in changes.iter().zip(states) {
+        if let (BoardChange::Rotate { layouts, .. }, 1..) = (change, state) {
+            layout = HashMap::new();
+            for &(from, to) in layouts[state - 1].iter() {
+                if layout.insert(from, to).is_some() {
+                    return Err(ConfigError::DuplicateSource(from));
+                }
+            }
+        }
+    }
+    for (change, &state) in changes.iter().zip(states) {
+        if let (
+            BoardChange::Move {
+                from, positions, ..
+            },
+            1..,
+        ) = (change, state)
+        {
+            // Nothing to move if a rotation took it away
+            if layout.remove(from).is_some() {
+                let (new_from, new_to) = positions[state - 1];
+                if layout.insert(new_from, new_to).is_some() {
+                    return Err(ConfigError::DuplicateSource(new_from));
+                }
+            }
+        }
+    }
+    for (change, &state) in changes.iter().zip(states) {
+        if let BoardChange::Break { from, after } = change {
+            if state >= *after {
+                layout.remove(from);
+            }
+        }
+    }
+    Ok(layout)
+}
+
+/// How many layouts all_layouts goes through for these changes, saturating at usize::MAX
+pub fn layout_count(changes: &[BoardChange]) -> usize {
+    changes
+        .iter()
+        .fold(1, |count, c| count.saturating_mul(c.states().len()))
+}
+
+/// Every layout the changes can lead to together, starting from routes.
+/// There are layout_count of them, so check that first
+pub fn all_layouts(
+    routes: &HashMap<usize, usize>,
+    changes: &[BoardChange],
+) -> Result<Vec<HashMap<usize, usize>>, ConfigError> {
+    let choices: Vec<Vec<usize>> = changes.iter().map(|c| c.states()).collect();
+    // Count through every combination of states, like an odometer
+    let mut picks = vec![0; changes.len()];
+    let mut layouts = vec![];
+    loop {
+        let states: Vec<usize> = picks.iter().zip(&choices).map(|(&i, c)| c[i]).collect();
+        layouts.push(combine(routes, changes, &states)?);
+        let Some(i) = (0..picks.len()).find(|&i| picks[i] + 1 < choices[i].len()) else {
+            return Ok(layouts);
+        };
+        picks[i] += 1;
+        picks[..i].fill(0);
+    }
+}
+
+/// The board as it is now in a game, and how it will change. A Game shares one between its players
+#[derive(Debug, Clone)]
+pub struct BoardState {
+    board: Board,
+    start: HashMap<usize, usize>, // The snakes and ladders the game started with
+    changes: Vec<BoardChange>,
+    states: Vec<usize>, // Of each change, see combine
+    players: usize,     // Turns in a round
+    turns: usize,       // Turns ended so far, by every player
+    version: usize,     // Times the layout has changed
+}
+
+impl BoardState {
+    /// The board at the start of a one player game, before any change
+    pub fn new(board: Board, changes: &[BoardChange]) -> BoardState {
+        BoardState {
+            start: board.routes.clone(),
+            board,
+            changes: changes.to_vec(),
+            states: vec![0; changes.len()],
+            players: 1,
+            turns: 0,
+            version: 0,
+        }
+    }
+
+    /// The same board for a game where players take turns in rounds
+    pub fn for_players(self, players: usize) -> BoardState {
+        BoardState {
+            players: players.max(1),
+            ..self
+        }
+    }
+
+    /// The layout right now
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Goes up every time the layout changes, so a player can tell it moved since they last looked
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// A player's turn is over, lost or not. Makes the changes due once the round is, true if the
+    /// layout changed
+    pub fn end_turn(&mut self) -> bool {
+        self.turns += 1;
+        if !self.turns.is_multiple_of(self.players) {
+            return false;
+        }
+        let round = self.turns / self.players;
+        for (change, state) in self.changes.iter().zip(self.states.iter_mut()) {
+            match change {
+                BoardChange::Move {
+                    every, positions, ..
+                } if round.is_multiple_of(*every) => *state = (*state + 1) % (positions.len() + 1),
+                BoardChange::Rotate { every, layouts } if round.is_multiple_of(*every) => {
+                    *state = (*state + 1) % (layouts.len() + 1)
+                }
+                _ => {}
+            }
+        }
+        self.relayout()
+    }
+
+    /// Count the snake or ladder from this square being taken by anyone, true if that broke it
+    pub fn took_route(&mut self, from: usize) -> bool {
+        for (change, state) in self.changes.iter().zip(self.states.iter_mut()) {
+            if matches!(*change, BoardChange::Break { from: f, .. } if f == from) {
+                *state += 1;
+            }
+        }
+        self.relayout()
+    }
+
+    /// Put the routes where the changes say, true if they moved. Config validation rules out
+    /// clashes, but with hand-built Rules a clashing layout is left as it was
+    fn relayout(&mut self) -> bool {
+        match combine(&self.start, &self.changes, &self.states) {
+            Ok(routes) if routes != self.board.routes => {
+                self.board.routes = routes;
+                self.version += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_move() {
+        let board = Board::new(20, HashMap::from([(15, 5), (3, 8)])).unwrap();
+        let change = BoardChange::Move {
+            from: 15,
+            every: 2,
+            positions: vec![(16, 2), (12, 1)],
+        };
+        let mut state = BoardState::new(board, &[change]);
+        assert!(!state.end_turn());
+        assert!(state.end_turn());
+        assert_eq!(state.board().routes, HashMap::from([(16, 2), (3, 8)]));
+        assert!(!state.end_turn());
+        assert!(state.end_turn());
+        assert_eq!(state.board().routes, HashMap::from([(12, 1), (3, 8)]));
+        state.end_turn();
+        assert!(state.end_turn());
+        assert_eq!(state.board().routes, HashMap::from([(15, 5), (3, 8)]));
+        assert_eq!(state.version(), 3);
+    }
+
+    #[test]
+    fn test_rounds() {
+        let board = Board::new(20, HashMap::from([(15, 5)])).unwrap();
+        let change = BoardChange::Move {
+            from: 15,
+            every: 1,
+            positions: vec![(16, 2)],
+        };
+        // Only once all three players have had a turn
+        let mut state = BoardState::new(board, &[change]).for_players(3);
+        assert!(!state.end_turn());
+        assert!(!state.end_turn());
+        assert!(state.end_turn());
+        assert_eq!(state.board().routes, HashMap::from([(16, 2)]));
+    }
+
+    #[test]
+    fn test_break() {
+        let board = Board::new(20, HashMap::from([(3, 8)])).unwrap();
+        let mut state = BoardState::new(board, &[BoardChange::Break { from: 3, after: 2 }]);
+        assert!(!state.took_route(3));
+        assert!(!state.took_route(5));
+        assert!(state.took_route(3));
+        assert!(state.board().routes.is_empty());
+        assert!(!state.took_route(3));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let board = Board::new(20, HashMap::from([(3, 8)])).unwrap();
+        let change = BoardChange::Rotate {
+            every: 1,
+            layouts: vec![vec![(12, 4)], vec![]],
+        };
+        let mut state = BoardState::new(board, &[change]);
+        assert!(state.end_turn());
+        assert_eq!(state.board().routes, HashMap::from([(12, 4)]));
+        assert!(state.end_turn());
+        assert!(state.board().routes.is_empty());
+        assert!(state.end_turn());
+        assert_eq!(state.board().routes, HashMap::from([(3, 8)]));
+    }
+
+    #[test]
+    fn test_all_layouts() {
+        let routes = HashMap::from([(15, 5), (3, 8)]);
+        let changes = [
+            BoardChange::Move {
+                from: 15,
+                every: 2,
+                positions: vec![(16, 2)],
+            },
+            BoardChange::Break { from: 3, after: 1 },
+        ];
+        let layouts = all_layouts(&routes, &changes).unwrap();
+        assert_eq!(
+            layouts,
+            vec![
+                routes.clone(),
+                HashMap::from([(16, 2), (3, 8)]),
+                HashMap::from([(15, 5)]),
+                HashMap::from([(16, 2)]),
+            ]
+        );
+        // Moving onto the ladder
+        let changes = [BoardChange::Move {
+            from: 15,
+            every: 2,
+            positions: vec![(3, 1)],
+        }];
+        assert_eq!(
+            all_layouts(&routes, &changes),
+            Err(ConfigError::DuplicateSource(3))
+        );
+    }
+
+    #[test]
+    fn test_layout_count() {
+        let changes = [
+            BoardChange::Move {
+                from: 15,
+                every: 2,
+                positions: vec![(16, 2), (12, 1)],
+            },
+            BoardChange::Break { from: 3, after: 4 },
+        ];
+        assert_eq!(layout_count(&changes), 6);
+        assert_eq!(layout_count(&[]), 1);
+        let breaks = vec![BoardChange::Break { from: 3, after: 1 }; 100];
+        assert_eq!(layout_count(&breaks), usize::MAX);
+    }
+
+    #[test]
+    fn test_check() {
+        let routes = HashMap::from([(15, 5), (3, 8)]);
+        let change = BoardChange::Break { from: 4, after: 1 };
+        assert_eq!(change.check(&routes), Err(ConfigError::NoRoute(4)));
+        let change = BoardChange::Rotate {
+            every: 0,
+            layouts: vec![vec![]],
+        };
+        assert_eq!(
+            change.check(&routes).unwrap_err().to_string(),
+            "Bad board change: every must be at least 1"
+        );
+    }
+}
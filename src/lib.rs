@@ -0,0 +1,39 @@
+//! Simulates games of snakes and ladders and reports stats about them.
+//!
+//! Load a board and rules from a config file with [`load_cfg`], or build a [`Board`] and
+//! [`Rules`] by hand, then play many games with [`run_sim_batch`] or one with [`play_game`].
+//! A [`Sim`] is a single player, rolling whatever [`Roll`] it is given.
+//!
+//! ```
+//! use snakes_and_ladders::{run_sim_batch, Board, Rules};
+//! use std::collections::HashMap;
+//!
+//! let board = Board::new(20, HashMap::from([(3, 11), (17, 4)])).unwrap();
+//! let results = run_sim_batch(board, Rules::default(), 100, 42, 1).unwrap();
+//! assert_eq!(results.seat_win_rates, vec![1.0]);
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod batch;
+pub mod boards;
+pub mod config;
+pub mod dice;
+pub mod error;
+pub mod game;
+pub mod layout;
+pub mod markov;
+pub mod output;
+pub mod render;
+pub mod sim;
+pub mod stats;
+pub mod svg;
+pub mod trace;
+
+pub use crate::batch::{play_game, run_sim_batch, trace_game, ExtremeSeeds, MultiSimResult};
+pub use crate::boards::Board;
+pub use crate::config::{load_cfg, parse_cfg, Config};
+pub use crate::dice::{Die, Roll};
+pub use crate::error::ConfigError;
+pub use crate::game::Game;
+pub use crate::sim::{Rules, Sim};
@@ -0,0 +1,527 @@
+//! Exact odds of how long a game lasts, worked out as a Markov chain.
+
+use crate::boards::{Board, Special};
+use crate::sim::{Penalty, Rules};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// Stop extending the game length distribution once this much probability is left unaccounted for
+const PMF_TOLERANCE: f64 = 1e-12;
+/// Hard limit on the length of the game length distribution, in rolls
+const PMF_MAX_ROLLS: usize = 100_000;
+
+/// Why a board can't be solved exactly
+#[derive(Debug)]
+pub enum MarkovError {
+    /// Some reachable square can never finish the game
+    Unwinnable,
+    /// See Rules::board_changes
+    DynamicBoard,
+}
+impl std::error::Error for MarkovError {}
+
+impl fmt::Display for MarkovError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MarkovError::Unwinnable => write!(f, "Some reachable square can never finish the game"),
+            MarkovError::DynamicBoard => write!(
+                f,
+                "Can't solve a board that changes during the game, simulate it instead"
+            ),
+        }
+    }
+}
+
+/// Exact statistics for a single player game, no sampling noise
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ExactResult {
+    /// Mean rolls to win
+    pub expected_rolls: f64,
+    /// Mean turns to win
+    pub expected_turns: f64,
+    /// Variance of the rolls to win
+    pub rolls_variance: f64,
+    /// `rolls_pmf[n]` is the chance the game takes exactly n rolls
+    pub rolls_pmf: Vec<f64>,
+}
+
+/// Everything about the game that matters for the next roll
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    position: usize,
+    mid_turn: bool,    // true if the next roll doesn't start a turn
+    rerolls: usize,    // Re-rolls the die has earned this turn, only tracked with a reroll limit
+    turn_start: usize, // Where the turn started, only tracked for Penalty::ReturnToStart
+    backwards: bool,   // The next roll moves backwards, see Special::Backwards
+    lose_turn: bool,   // Mid turn, the next turn will be skipped. Otherwise, it just was
+}
+
+impl State {
+    fn start_turn(position: usize, lose_turn: bool) -> State {
+        State {
+            position,
+            mid_turn: false,
+            rerolls: 0,
+            turn_start: position,
+            backwards: false,
+            lose_turn,
+        }
+    }
+}
+
+/// Mirrors Sim::turn_roll, without the stats. Each state the roll can lead to and its chance,
+/// None if the roll wins
+fn next_states(
+    board: &Board,
+    rules: &Rules,
+    state: State,
+    die_value: usize,
+    reroll: bool,
+) -> Vec<(Option<State>, f64)> {
+    let lose_turn = state.mid_turn && state.lose_turn;
+    let finished = |position: usize, next: State| (position != board.size).then_some(next);
+    if state.backwards {
+        return board
+            .destinations(state.position.saturating_sub(die_value))
+            .into_iter()
+            .map(|(position, p)| {
+                (
+                    finished(position, State::start_turn(position, lose_turn)),
+                    p,
+                )
+            })
+            .collect();
+    }
+    if let Some(limit) = rules.reroll_limit {
+        if reroll && state.rerolls >= limit.max {
+            // Mirrors Sim::roll_penalty
+            let next = match limit.penalty {
+                Penalty::Forfeit => State::start_turn(state.position, lose_turn),
+                Penalty::ReturnToStart => State::start_turn(state.turn_start, lose_turn),
+            };
+            return vec![(Some(next), 1.0)];
+        }
+    }
+    // Where the roll ends up after any snake or ladder, and the special square there
+    let landings: Vec<(usize, Option<Special>, f64)> =
+        match rules.overshoot.land(board.size, state.position, die_value) {
+            Some(rolled) => board
+                .destinations(rolled)
+                .into_iter()
+                .map(|(position, p)| (position, board.specials.get(&position).copied(), p))
+                .collect(),
+            None => vec![(state.position, None, 1.0)], // Illegal move!
+        };
+    landings
+        .into_iter()
+        .map(|(position, special, p)| {
+            let lose_turn = lose_turn || special == Some(Special::LoseTurn);
+            let square = match special {
+                Some(Special::BackToStart) => 0,
+                _ => position,
+            };
+            let again = State {
+                position: square,
+                mid_turn: true,
+                rerolls: match rules.reroll_limit {
+                    Some(limit) => (state.rerolls + reroll as usize).min(limit.max),
+                    None => 0,
+                },
+                turn_start: state.turn_start,
+                backwards: false,
+                lose_turn,
+            };
+            let next = match special {
+                Some(Special::ExtraRoll) => again,
+                Some(Special::Backwards) => State {
+                    backwards: true,
+                    ..again
+                },
+                Some(Special::SwapWithLeader) => State::start_turn(square, lose_turn),
+                _ if reroll => again,
+                _ => State::start_turn(square, lose_turn),
+            };
+            (finished(position, next), p)
+        })
+        .collect()
+}
+
+/// The absorbing chain over all reachable non-winning states
+struct Chain {
+    states: Vec<State>,
+    // Row per state: (index of next state, probability). Winning moves are left out.
+    transitions: Vec<Vec<(usize, f64)>>,
+    finish: Vec<f64>, // Chance of winning on the next roll, from each state
+}
+
+impl Chain {
+    fn build(board: &Board, rules: &Rules) -> Chain {
+        let outcomes = rules.die.roll_outcomes();
+        let track_start = matches!(
+            rules.reroll_limit.map(|limit| limit.penalty),
+            Some(Penalty::ReturnToStart)
+        );
+        let start = State::start_turn(0, false);
+        let mut index: HashMap<State, usize> = HashMap::from([(start, 0)]);
+        let mut states = vec![start];
+        let mut transitions = vec![];
+        let mut finish = vec![];
+        let mut queue = VecDeque::from([start]);
+        while let Some(state) = queue.pop_front() {
+            let mut row: HashMap<usize, f64> = HashMap::new();
+            let mut finish_p = 0.0;
+            let nexts = outcomes.iter().flat_map(|&(die_value, p, reroll)| {
+                next_states(board, rules, state, die_value, reroll)
+                    .into_iter()
+                    .map(move |(next, q)| (next, p * q))
+            });
+            for (next, p) in nexts {
+                let Some(next) = next else {
+                    finish_p += p; // Absorbed, no need to track
+                    continue;
+                };
+                // Fewer states if the start doesn't matter
+                let next = if track_start {
+                    next
+                } else {
+                    State {
+                        turn_start: 0,
+                        ..next
+                    }
+                };
+                let next_idx = *index.entry(next).or_insert_with(|| {
+                    states.push(next);
+                    queue.push_back(next);
+                    states.len() - 1
+                });
+                *row.entry(next_idx).or_insert(0.0) += p;
+            }
+            transitions.push(row.into_iter().collect());
+            finish.push(finish_p);
+        }
+        Chain {
+            states,
+            transitions,
+            finish,
+        }
+    }
+
+    /// Dense copy of I - Q, where Q is the transient part of the transition matrix
+    fn fundamental_system(&self) -> Vec<Vec<f64>> {
+        let n = self.states.len();
+        let mut m = vec![vec![0.0; n]; n];
+        for (i, row) in self.transitions.iter().enumerate() {
+            m[i][i] += 1.0;
+            for &(j, p) in row.iter() {
+                m[i][j] -= p;
+            }
+        }
+        m
+    }
+
+    /// Q * x
+    fn step(&self, x: &[f64]) -> Vec<f64> {
+        self.transitions
+            .iter()
+            .map(|row| row.iter().map(|&(j, p)| p * x[j]).sum())
+            .collect()
+    }
+
+    /// Chance of finishing after exactly n rolls, for every n until the tail is negligible
+    fn rolls_pmf(&self) -> Vec<f64> {
+        let mut pmf = vec![0.0]; // Can't win without rolling
+        let mut dist = vec![0.0; self.states.len()];
+        dist[0] = 1.0;
+        let mut remaining = 1.0;
+        while remaining > PMF_TOLERANCE && pmf.len() <= PMF_MAX_ROLLS {
+            let mut next = vec![0.0; self.states.len()];
+            for (i, row) in self.transitions.iter().enumerate() {
+                for &(j, p) in row.iter() {
+                    next[j] += dist[i] * p;
+                }
+            }
+            pmf.push(
+                dist.iter()
+                    .zip(self.finish.iter())
+                    .map(|(d, f)| d * f)
+                    .sum(),
+            );
+            remaining = next.iter().sum();
+            dist = next;
+        }
+        pmf
+    }
+}
+
+/// Solve m * x = rhs for several right hand sides at once, by Gaussian elimination
+/// Returns None if m is singular
+fn solve_linear(mut m: Vec<Vec<f64>>, mut rhs: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = m.len();
+    for col in 0..n {
+        // Partial pivoting
+        let pivot = (col..n).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+        for r in rhs.iter_mut() {
+            r.swap(col, pivot);
+        }
+        let pivot_row = m[col].clone();
+        for row in col + 1..n {
+            let factor = m[row][col] / pivot_row[col];
+            if factor == 0.0 {
+                continue;
+            }
+            for (x, p) in m[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *x -= factor * p;
+            }
+            for r in rhs.iter_mut() {
+                r[row] -= factor * r[col];
+            }
+        }
+    }
+    // Back substitution
+    for r in rhs.iter_mut() {
+        for row in (0..n).rev() {
+            let tail: f64 = (row + 1..n).map(|k| m[row][k] * r[k]).sum();
+            r[row] = (r[row] - tail) / m[row][row];
+        }
+    }
+    Some(rhs)
+}
+
+/// Compute the exact game length statistics for a single player on this board.
+/// Only the die, overshoot, reroll limit and special squares matter, there's no one else to race and
+/// no turn limit. Swapping with the leader just ends the turn
+pub fn solve(board: &Board, rules: &Rules) -> Result<ExactResult, MarkovError> {
+    if !rules.board_changes.is_empty() {
+        return Err(MarkovError::DynamicBoard);
+    }
+    let chain = Chain::build(board, rules);
+    let ones = vec![1.0; chain.states.len()];
+    let turn_starts = chain
+        .states
+        .iter()
+        .map(|s| match (s.mid_turn, s.lose_turn) {
+            (true, _) => 0.0,
+            (false, true) => 2.0, // The lost turn is skipped first
+            (false, false) => 1.0,
+        })
+        .collect();
+    let solved = solve_linear(chain.fundamental_system(), vec![ones.clone(), turn_starts])
+        .ok_or(MarkovError::Unwinnable)?;
+    let (rolls, turns) = (&solved[0], &solved[1]);
+
+    // Second moment s solves (I - Q) s = 1 + 2 Q t
+    let second_rhs = chain
+        .step(rolls)
+        .iter()
+        .zip(ones)
+        .map(|(qt, one)| one + 2.0 * qt)
+        .collect();
+    let second_moment = solve_linear(chain.fundamental_system(), vec![second_rhs])
+        .ok_or(MarkovError::Unwinnable)?;
+
+    Ok(ExactResult {
+        expected_rolls: rolls[0],
+        expected_turns: turns[0],
+        rolls_variance: second_moment[0][0] - rolls[0] * rolls[0],
+        rolls_pmf: chain.rolls_pmf(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boards::{blank, canon_board, Overshoot, RandomRoute, Special};
+    use crate::dice::{Die, Faces, RerollOn};
+    use crate::layout::BoardChange;
+    use crate::sim::RerollLimit;
+
+    fn with_die(die: Die) -> Rules {
+        Rules {
+            die,
+            ..Rules::default()
+        }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_single_square() {
+        // Only a 1 wins, so the game length is geometric with p = 1/6
+        let result = solve(&blank(1), &Rules::default()).unwrap();
+        assert_close(result.expected_rolls, 6.0);
+        assert_close(result.rolls_variance, 30.0);
+        // A 6 is a re-roll within the same turn
+        assert_close(result.expected_turns, 5.0);
+        assert_close(result.rolls_pmf[1], 1.0 / 6.0);
+        assert_close(result.rolls_pmf[2], 5.0 / 36.0);
+    }
+
+    #[test]
+    fn test_custom_die() {
+        let die = Die {
+            reroll_on: RerollOn::Never,
+            ..Die::numbered(4)
+        };
+        let result = solve(&blank(1), &with_die(die)).unwrap();
+        assert_close(result.expected_rolls, 4.0);
+        assert_close(result.expected_turns, 4.0);
+    }
+
+    #[test]
+    fn test_overshoot() {
+        let die = Die {
+            faces: Faces::Values(vec![1, 3]),
+            reroll_on: RerollOn::Never,
+            ..Die::default()
+        };
+        let stay = solve(&blank(4), &with_die(die.clone())).unwrap();
+        assert_close(stay.expected_rolls, 3.5);
+        // A 3 from square 2 bounces back to 3 instead of staying on 2, then 3 from 3 goes back to 2
+        let rules = Rules {
+            overshoot: Overshoot::Bounce,
+            ..with_die(die.clone())
+        };
+        assert_close(solve(&blank(4), &rules).unwrap().expected_rolls, 4.0);
+        let rules = Rules {
+            overshoot: Overshoot::Finish,
+            ..with_die(die)
+        };
+        // Any 3 wins from square 1 up
+        assert!(solve(&blank(4), &rules).unwrap().expected_rolls < 3.5);
+    }
+
+    #[test]
+    fn test_reroll_limit() {
+        // A 2 re-rolls once, a second 2 in the turn is the penalty
+        let rules = |penalty| Rules {
+            reroll_limit: Some(RerollLimit { max: 1, penalty }),
+            ..with_die(Die::numbered(2))
+        };
+        let forfeit = solve(&blank(3), &rules(Penalty::Forfeit)).unwrap();
+        assert_close(forfeit.expected_rolls, 3.0);
+        // 2 then 2 from the start goes back to 0 rather than staying on 2
+        let back = solve(&blank(3), &rules(Penalty::ReturnToStart)).unwrap();
+        assert_close(back.expected_rolls, 10.0 / 3.0);
+        assert_close(back.rolls_pmf.iter().sum(), 1.0);
+    }
+
+    #[test]
+    fn test_specials() {
+        // On 3 squares, a d2 takes 3 rolls and 3 turns without specials
+        let rules = with_die(Die {
+            reroll_on: RerollOn::Never,
+            ..Die::numbered(2)
+        });
+        let solve_with = |special| {
+            let board = Board::with_specials(3, HashMap::new(), HashMap::from([special])).unwrap();
+            solve(&board, &rules).unwrap()
+        };
+        let result = solve_with((1, Special::ExtraRoll));
+        assert_close(result.expected_rolls, 3.0);
+        assert_close(result.expected_turns, 2.5);
+        let result = solve_with((1, Special::LoseTurn));
+        assert_close(result.expected_rolls, 3.0);
+        assert_close(result.expected_turns, 3.5);
+        assert_close(solve_with((2, Special::BackToStart)).expected_rolls, 6.0);
+        assert_close(solve_with((2, Special::Backwards)).expected_rolls, 6.0);
+        // Nobody to swap with, it only ends the turn
+        let result = solve_with((1, Special::SwapWithLeader));
+        assert_close(result.expected_turns, 3.0);
+    }
+
+    #[test]
+    fn test_random_routes() {
+        // Always move 1, so it's 3 rolls unless a random route from 1 cuts it short
+        let rules = with_die(Die {
+            faces: Faces::Values(vec![1]),
+            reroll_on: RerollOn::Never,
+            ..Die::default()
+        });
+        let solve_with = |chance, to| {
+            let random = HashMap::from([(1, RandomRoute { chance, to })]);
+            let board = blank(3).with_random_routes(random).unwrap();
+            solve(&board, &rules).unwrap()
+        };
+        assert_close(solve_with(0.5, vec![(3, 1.0)]).expected_rolls, 2.0);
+        assert_close(
+            solve_with(1.0, vec![(3, 1.0), (2, 1.0)]).expected_rolls,
+            1.5,
+        );
+    }
+
+    #[test]
+    fn test_dynamic_board() {
+        let rules = Rules {
+            board_changes: vec![BoardChange::Break { from: 4, after: 1 }],
+            ..Rules::default()
+        };
+        assert!(matches!(
+            solve(&canon_board(), &rules),
+            Err(MarkovError::DynamicBoard)
+        ));
+    }
+
+    #[test]
+    fn test_two_dice() {
+        // Need a double 1 to win, and a double re-rolls
+        let die = Die {
+            count: 2,
+            reroll_on: RerollOn::AnyDouble,
+            ..Die::default()
+        };
+        let result = solve(&blank(2), &with_die(die)).unwrap();
+        assert_close(result.expected_rolls, 36.0);
+        // After the first roll, 5 in 35 of the rolls that didn't win were doubles
+        assert_close(result.expected_turns, 1.0 + 35.0 * 30.0 / 35.0);
+    }
+
+    #[test]
+    fn test_weighted_die() {
+        // A loaded coin, 1 in 4 chance of moving
+        let die = Die {
+            faces: Faces::Values(vec![0, 1]),
+            count: 1,
+            weights: Some(vec![3.0, 1.0]),
+            reroll_on: RerollOn::Never,
+        };
+        let result = solve(&blank(1), &with_die(die)).unwrap();
+        assert_close(result.expected_rolls, 4.0);
+        assert_close(result.rolls_variance, 12.0);
+    }
+
+    #[test]
+    fn test_ladder_to_finish() {
+        // Every roll lands on a ladder to the finish
+        let board = Board::new(
+            7,
+            HashMap::from([(1, 7), (2, 7), (3, 7), (4, 7), (5, 7), (6, 7)]),
+        )
+        .unwrap();
+        let result = solve(&board, &Rules::default()).unwrap();
+        assert_close(result.expected_rolls, 1.0);
+        assert_close(result.rolls_variance, 0.0);
+        assert_eq!(result.rolls_pmf.len(), 2);
+        assert_close(result.rolls_pmf[1], 1.0);
+    }
+
+    #[test]
+    fn test_canon_board_pmf_consistent() {
+        let result = solve(&canon_board(), &Rules::default()).unwrap();
+        assert_close(result.rolls_pmf.iter().sum(), 1.0);
+        let pmf_mean: f64 = result
+            .rolls_pmf
+            .iter()
+            .enumerate()
+            .map(|(n, p)| n as f64 * p)
+            .sum();
+        assert!((pmf_mean - result.expected_rolls).abs() < 1e-6);
+        assert!(result.expected_turns < result.expected_rolls);
+    }
+}
@@ -0,0 +1,172 @@
+//! Formatting results as Debug, JSON, CSV or Markdown.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::{fmt, fs, io};
+
+/// How results are written out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Rust's {:?}, the original output
+    Debug,
+    /// One JSON object
+    Json,
+    /// Header row then one row per batch
+    Csv,
+    /// Two column table of stat and value
+    Markdown,
+}
+
+/// A format name that isn't one of Format's
+#[derive(Debug)]
+pub struct BadFormatError(String);
+impl std::error::Error for BadFormatError {}
+
+impl fmt::Display for BadFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Unknown output format {}, try debug, json, csv or markdown",
+            self.0
+        )
+    }
+}
+
+impl FromStr for Format {
+    type Err = BadFormatError;
+
+    fn from_str(s: &str) -> Result<Format, BadFormatError> {
+        match s.to_lowercase().as_str() {
+            "debug" => Ok(Format::Debug),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "markdown" | "md" => Ok(Format::Markdown),
+            _ => Err(BadFormatError(s.to_string())),
+        }
+    }
+}
+
+/// Nested fields become a_b, lists stay as JSON text so they fit in one cell
+fn flatten(value: &Value, prefix: &str, cells: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, inner) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}_{}", prefix, key)
+                };
+                flatten(inner, &key, cells);
+            }
+        }
+        Value::Null => cells.push((prefix.to_string(), String::new())),
+        Value::String(s) => cells.push((prefix.to_string(), s.clone())),
+        other => cells.push((prefix.to_string(), other.to_string())),
+    }
+}
+
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// The result written out in the given format
+pub fn render<T: Serialize + Debug>(result: &T, format: Format) -> String {
+    if format == Format::Debug {
+        return format!("{:?}\n", result);
+    }
+    let value = serde_json::to_value(result).unwrap();
+    if format == Format::Json {
+        return serde_json::to_string_pretty(&value).unwrap() + "\n";
+    }
+    let mut cells = vec![];
+    flatten(&value, "", &mut cells);
+    match format {
+        Format::Csv => {
+            let (header, row): (Vec<String>, Vec<String>) = cells
+                .iter()
+                .map(|(k, v)| (csv_escape(k), csv_escape(v)))
+                .unzip();
+            format!("{}\n{}\n", header.join(","), row.join(","))
+        }
+        _ => {
+            let mut table = String::from("| Stat | Value |\n| --- | --- |\n");
+            for (key, value) in cells {
+                table += &format!("| {} | {} |\n", key, value.replace('|', "\\|"));
+            }
+            table
+        }
+    }
+}
+
+/// Write to the file if there is one, otherwise stdout
+pub fn write(text: &str, file: Option<&str>) -> io::Result<()> {
+    match file {
+        Some(file) => fs::write(file, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Serialize)]
+    struct Inner {
+        median: usize,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct Example {
+        rolls: f64,
+        turn: Vec<Vec<usize>>,
+        seed: Option<u64>,
+        dist: Inner,
+    }
+
+    fn example() -> Example {
+        Example {
+            rolls: 1.5,
+            turn: vec![vec![6], vec![2]],
+            seed: None,
+            dist: Inner { median: 3 },
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            render(&example(), Format::Csv),
+            "rolls,turn,seed,dist_median\n1.5,\"[[6],[2]]\",,3\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            render(&example(), Format::Markdown),
+            "| Stat | Value |\n| --- | --- |\n| rolls | 1.5 |\n| turn | [[6],[2]] |\n| seed |  |\n| dist_median | 3 |\n"
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let json: Value = serde_json::from_str(&render(&example(), Format::Json)).unwrap();
+        assert_eq!(json["dist"]["median"], 3);
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("JSON".parse::<Format>().unwrap(), Format::Json);
+        assert_eq!("md".parse::<Format>().unwrap(), Format::Markdown);
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Drawing the board and heatmaps as text.
+
+use crate::boards::Board;
+use crate::sim::calc_lucky_spaces;
+use crate::stats::Heatmap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Darkest last, for the heatmap overlay
+const SHADES: &[char] = &[' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+/// Extra information drawn in the corner of each square
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overlay {
+    /// Just the square numbers
+    None,
+    /// + for a lucky square, - for an unlucky one, see sim::calc_lucky_spaces
+    Luck,
+    /// How often rolls land on the square, darker is more often
+    Heatmap,
+}
+
+/// An overlay name that isn't one of Overlay's
+#[derive(Debug)]
+pub struct BadOverlayError(String);
+impl std::error::Error for BadOverlayError {}
+
+impl fmt::Display for BadOverlayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown overlay {}, try none, luck or heatmap", self.0)
+    }
+}
+
+impl FromStr for Overlay {
+    type Err = BadOverlayError;
+
+    fn from_str(s: &str) -> Result<Overlay, BadOverlayError> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Overlay::None),
+            "luck" => Ok(Overlay::Luck),
+            "heatmap" => Ok(Overlay::Heatmap),
+            _ => Err(BadOverlayError(s.to_string())),
+        }
+    }
+}
+
+/// Squares per row, 10 for the usual 100 square board
+pub(crate) fn grid_width(size: usize) -> usize {
+    let mut width = 1;
+    while width * width < size {
+        width += 1;
+    }
+    width
+}
+
+/// Column from the left and row from the bottom of a square, for a board of the given size.
+/// Rows zig-zag, so square 1 is bottom left and the next row starts above the end of this one
+pub(crate) fn grid_position(size: usize, square: usize) -> (usize, usize) {
+    let width = grid_width(size);
+    let (row, offset) = ((square - 1) / width, (square - 1) % width);
+    match row % 2 {
+        0 => (offset, row),
+        _ => (width - 1 - offset, row),
+    }
+}
+
+/// How busy each square is compared to the busiest, from 0 to 1.
+/// Every winner lands on the last square, so it's left out of the scale
+pub(crate) fn heat_scale(board: &Board, heatmap: &Heatmap) -> Vec<f64> {
+    let squares = heatmap.landed_on.iter().take(board.size);
+    let most = squares.copied().fold(0.0, f64::max);
+    (0..=board.size)
+        .map(|square| {
+            let chance = heatmap.landed_on.get(square).copied().unwrap_or(0.0);
+            if most > 0.0 {
+                (chance / most).min(1.0)
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// (from, to) of each snake or ladder
+pub(crate) type Routes = Vec<(usize, usize)>;
+
+/// Snakes and ladders numbered from the bottom of the board, e.g. S1 is the snake with the lowest head.
+/// Each way a random route can go is numbered on its own
+pub(crate) fn numbered_routes(board: &Board) -> (Routes, Routes) {
+    board
+        .all_routes()
+        .into_iter()
+        .partition(|&(from, to)| to < from)
+}
+
+/// The board as rows of squares, square 1 bottom left and zig-zagging up like a printed board.
+/// Route starts are upper case and ends lower case, so a snake runs from S1 down to s1 and
+/// a ladder from L1 up to l1. Overlay::Heatmap shades by landed_on, and is left out without a heatmap
+pub fn render_board(board: &Board, overlay: Overlay, heatmap: Option<&Heatmap>) -> String {
+    let (snakes, ladders) = numbered_routes(board);
+    let mut markers = vec![String::new(); board.size + 1];
+    for (prefix, routes) in [("S", &snakes), ("L", &ladders)] {
+        for (i, &(from, to)) in routes.iter().enumerate() {
+            markers[from] += &format!("{}{}", prefix, i + 1);
+            markers[to] += &format!("{}{}", prefix.to_lowercase(), i + 1);
+        }
+    }
+    let corner: Vec<Option<char>> = match (overlay, heatmap) {
+        (Overlay::Luck, _) => {
+            let (lucky, unlucky) = calc_lucky_spaces(board);
+            (0..=board.size)
+                .map(|square| match square {
+                    _ if unlucky.contains(&square) => Some('-'),
+                    _ if lucky.contains(&square) => Some('+'),
+                    _ => Some(' '),
+                })
+                .collect()
+        }
+        (Overlay::Heatmap, Some(heatmap)) => heat_scale(board, heatmap)
+            .into_iter()
+            .map(|shade| Some(SHADES[(shade * (SHADES.len() - 1) as f64).round() as usize]))
+            .collect(),
+        _ => vec![None; board.size + 1],
+    };
+
+    let number_width = board.size.to_string().len();
+    let marker_width = markers.iter().map(|m| m.len()).max().unwrap_or(0);
+    let cell = |square: usize| {
+        if square > board.size {
+            let corner_width = if corner[0].is_some() { 2 } else { 0 };
+            return " ".repeat(number_width + marker_width + corner_width + 3);
+        }
+        let mut text = format!(
+            " {:>nw$} {:<mw$} ",
+            square,
+            markers[square],
+            nw = number_width,
+            mw = marker_width
+        );
+        if let Some(c) = corner[square] {
+            text.push(c);
+            text.push(' ');
+        }
+        text
+    };
+
+    let width = grid_width(board.size);
+    let rows = board.size.div_ceil(width);
+    let cell_width = cell(board.size).len();
+    let separator = format!(
+        "+{}\n",
+        format!("{}+", "-".repeat(cell_width)).repeat(width)
+    );
+    let mut grid = vec![vec![board.size + 1; width]; rows]; // Off the board until filled in
+    for square in 1..=board.size {
+        let (col, row) = grid_position(board.size, square);
+        grid[row][col] = square;
+    }
+    let mut text = separator.clone();
+    for squares in grid.into_iter().rev() {
+        let cells: Vec<String> = squares.into_iter().map(cell).collect();
+        text += &format!("|{}|\n", cells.join("|"));
+        text += &separator;
+    }
+
+    for (prefix, routes) in [("S", &snakes), ("L", &ladders)] {
+        for (i, (from, to)) in routes.iter().enumerate() {
+            text += &format!("{}{}: {} -> {}", prefix, i + 1, from, to);
+            if board.random_routes.contains_key(from) {
+                text += &format!(" ({:.0}%)", 100.0 * board.route_chance(*from, *to));
+            }
+            text += "\n";
+        }
+    }
+    match (overlay, heatmap) {
+        (Overlay::Luck, _) => text += "+ lucky square, - unlucky square\n",
+        (Overlay::Heatmap, Some(_)) => {
+            text += &format!(
+                "Chance of landing on the square, from '{}' never to '{}' most often\n",
+                SHADES[0],
+                SHADES[SHADES.len() - 1]
+            )
+        }
+        _ => {}
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boards::{canon_board, RandomRoute};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_grid_width() {
+        assert_eq!(grid_width(100), 10);
+        assert_eq!(grid_width(6), 3);
+        assert_eq!(grid_width(1), 1);
+    }
+
+    #[test]
+    fn test_grid_position() {
+        assert_eq!(grid_position(100, 1), (0, 0));
+        assert_eq!(grid_position(100, 11), (9, 1));
+        assert_eq!(grid_position(100, 100), (0, 9));
+        assert_eq!(grid_position(8, 7), (0, 2));
+    }
+
+    #[test]
+    fn test_render_small_board() {
+        let board = Board::new(8, HashMap::from([(2, 6), (7, 1)])).unwrap();
+        assert_eq!(
+            render_board(&board, Overlay::None, None),
+            "\
++------+------+------+
+| 7 S1 | 8    |      |
++------+------+------+
+| 6 l1 | 5    | 4    |
++------+------+------+
+| 1 s1 | 2 L1 | 3    |
++------+------+------+
+S1: 7 -> 1
+L1: 2 -> 6
+"
+        );
+    }
+
+    #[test]
+    fn test_render_luck() {
+        let board = Board::new(4, HashMap::from([(3, 1)])).unwrap();
+        assert_eq!(
+            render_board(&board, Overlay::Luck, None),
+            "\
++--------+--------+
+| 4    + | 3 S1 - |
++--------+--------+
+| 1 s1 + | 2    + |
++--------+--------+
+S1: 3 -> 1
++ lucky square, - unlucky square
+"
+        );
+    }
+
+    #[test]
+    fn test_render_heatmap() {
+        let board = Board::new(4, HashMap::new()).unwrap();
+        let heatmap = Heatmap {
+            landed_on: vec![0.0, 0.5, 0.25, 0.0, 1.0],
+            ended_on: vec![0.0, 0.5, 0.25, 0.0, 1.0],
+            routes: vec![],
+        };
+        let text = render_board(&board, Overlay::Heatmap, Some(&heatmap));
+        // Scaled to the busiest square before the finish
+        assert!(text.contains("| 4  @ | 3    |"));
+        assert!(text.contains("| 1  @ | 2  + |"));
+        // Nothing to shade with
+        assert_eq!(
+            render_board(&board, Overlay::Heatmap, None),
+            render_board(&board, Overlay::None, None)
+        );
+    }
+
+    #[test]
+    fn test_render_random_routes() {
+        let random = RandomRoute {
+            chance: 0.5,
+            to: vec![(1, 1.0), (7, 1.0)],
+        };
+        let board = Board::new(8, HashMap::new())
+            .unwrap()
+            .with_random_routes(HashMap::from([(4, random)]))
+            .unwrap();
+        let text = render_board(&board, Overlay::None, None);
+        assert!(text.contains("| 4 S1L1 |"));
+        assert!(text.ends_with("S1: 4 -> 1 (25%)\nL1: 4 -> 7 (25%)\n"));
+    }
+
+    #[test]
+    fn test_render_canon_board() {
+        let text = render_board(&canon_board(), Overlay::None, None);
+        // Top row runs right to left, from 100
+        assert!(text.starts_with("+"));
+        assert!(text
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("| 100    |  99 S8 |"));
+        assert!(text.contains("S8: 99 -> 41\n"));
+        assert!(text.contains("L7: 74 -> 92\n"));
+    }
+
+    #[test]
+    fn test_parse_overlay() {
+        assert_eq!("Heatmap".parse::<Overlay>().unwrap(), Overlay::Heatmap);
+        assert!("colour".parse::<Overlay>().is_err());
+    }
+}
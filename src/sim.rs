@@ -0,0 +1,1060 @@
+//! A single player moving around the board, and the rules they play by.
+
+use crate::boards::{Board, Overshoot, Special};
+use crate::dice::{Die, RerollCheck, Roll};
+use crate::layout::{BoardChange, BoardState};
+use crate::trace::{Event, Luck};
+use serde::{Deserialize, Serialize};
+use std::cmp::{max, Ordering};
+use std::collections::{HashMap, HashSet};
+
+/// House rules for a game
+#[derive(Debug, Clone)]
+pub struct Rules {
+    /// Give up after this many turns, None to play until someone wins
+    pub max_turns: Option<usize>,
+    /// Players per game, see Game
+    pub players: usize,
+    /// The dice every player rolls
+    pub die: Die,
+    /// What a roll past the last square does
+    pub overshoot: Overshoot,
+    /// None to re-roll for as long as the die says so
+    pub reroll_limit: Option<RerollLimit>,
+    /// Empty for a board that stays the same all game
+    pub board_changes: Vec<BoardChange>,
+}
+
+/// What happens to a roll that would earn one re-roll too many
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Penalty {
+    /// That roll doesn't move, and the turn ends
+    Forfeit,
+    /// Back to the square the turn started on, and the turn ends
+    ReturnToStart,
+}
+
+/// Caps the re-rolls in a turn, e.g. three sixes in a row is max 2
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RerollLimit {
+    /// Re-rolls allowed per turn
+    pub max: usize,
+    /// What happens to the roll after that
+    pub penalty: Penalty,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            max_turns: None,
+            players: 1,
+            die: Die::default(),
+            overshoot: Overshoot::Stay,
+            reroll_limit: None,
+            board_changes: vec![],
+        }
+    }
+}
+
+/// One player's token on the board, and the stats of its game so far
+pub struct Sim {
+    board: BoardState,
+    rules: Rules,
+    position: usize,
+    rng: Box<dyn Roll>,
+    reroll_check: RerollCheck, // From rules.die, so rolls don't work it out every time
+    lucky_spaces: HashSet<usize>,
+    unlucky_spaces: HashSet<usize>,
+    lucky_version: usize, // BoardState::version the (un)lucky spaces were worked out for
+    this_turn: TurnInProgress,
+    lose_next_turn: bool, // Landed on Special::LoseTurn
+    swap_pending: bool,   // Landed on Special::SwapWithLeader, see Game::run
+    // stats
+    /// Turns taken, including lost ones
+    pub turn_count: usize,
+    /// Rolls made, including re-rolls
+    pub roll_count: usize,
+    /// Ladders climbed
+    pub climb_count: usize,
+    /// Snakes slid down
+    pub slide_count: usize,
+    /// Total squares climbed up ladders
+    pub climb_distance: usize,
+    /// Total squares slid down snakes
+    pub slide_distance: usize,
+    /// Most squares climbed in one turn
+    pub biggest_climb: usize,
+    /// Most squares slid in one turn
+    pub biggest_slide: usize,
+    /// The longest turn so far, the dice of each roll in it
+    pub longest_turn: Vec<Vec<usize>>,
+    /// Rolls onto a lucky square, see calc_lucky_spaces
+    pub lucky_rolls: usize,
+    /// Rolls onto an unlucky square, see calc_lucky_spaces
+    pub unlucky_rolls: usize,
+    /// Hit max_turns before winning
+    pub abandoned: bool,
+    /// Times each square was rolled onto, before any snake or ladder
+    pub landed_on: Vec<usize>,
+    /// Times each square was where a roll finished, after snakes and ladders
+    pub ended_on: Vec<usize>,
+    /// Times each snake or ladder was taken, by (from, to)
+    pub route_uses: HashMap<(usize, usize), usize>,
+    /// Times each special square took effect, by square
+    pub special_uses: HashMap<usize, usize>,
+    /// Turns skipped because of Special::LoseTurn
+    pub turns_lost: usize,
+    /// Times this player swapped squares with the leader
+    pub swaps: usize,
+    /// Replays the whole game, see play_game
+    pub seed: Option<u64>,
+    /// Position in the Game's turn order
+    pub seat: usize,
+    /// Every roll, only kept if Some, see record_trace
+    pub trace: Option<Vec<Event>>,
+}
+
+/// Stats of the turn being played
+#[derive(Default)]
+struct TurnInProgress {
+    start: usize, // Where the turn started, see Penalty::ReturnToStart
+    climb: usize,
+    slide: usize,
+    dice: Vec<Vec<usize>>, // Each roll so far
+    rerolls: usize,        // Re-rolls the die has earned so far, see RerollLimit
+    backwards: bool,       // The next roll moves backwards, see Special::Backwards
+}
+
+struct RollResult {
+    dice: Vec<usize>,
+    climb_distance: usize,
+    slide_distance: usize,
+    rolled: usize,               // Where the dice took us, before any snake or ladder
+    routes: Vec<(usize, usize)>, // Each snake or ladder followed, in order. (from, from) if a random one didn't fire
+    luck: Luck,
+    penalty: bool,            // Went over the reroll limit instead of moving
+    special: Option<Special>, // Took effect where the roll finished
+}
+
+/// Orders turns by length, i.e. the number of rolls, then by the dice rolled
+/// e.g. `[[6], [5]] < [[6], [6], [2]] < [[6], [6], [3]]`
+pub fn cmp_turns(a: &[Vec<usize>], b: &[Vec<usize>]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// The (un)lucky squares of a board, lucky first
+/// Currently a roll is (un)lucky iff it lands on an (un)lucky space
+/// A random route is neither, the way it goes decides, but counts as a snake for near-misses if it can go down
+pub fn calc_lucky_spaces(board: &Board) -> (HashSet<usize>, HashSet<usize>) {
+    let mut lucky_spaces: HashSet<usize> = HashSet::new();
+    let mut unlucky_spaces: HashSet<usize> = HashSet::new();
+    let goes_down = |square: usize| match board.random_routes.get(&square) {
+        Some(route) => route.to.iter().any(|&(to, w)| to < square && w > 0.0),
+        None => *board.routes.get(&square).unwrap_or(&square) < square,
+    };
+    for i in 0..board.size {
+        // lucky or unlucky if ladder or snake.
+        match board.routes.get(&i).unwrap_or(&i).cmp(&i) {
+            Ordering::Greater => {
+                lucky_spaces.insert(i);
+            }
+            Ordering::Less => {
+                unlucky_spaces.insert(i);
+            }
+            Ordering::Equal => {}
+        }
+        // Check for snake near-miss
+        for delta in [-2, -1, 1, 2] {
+            let other_i = i as isize + delta;
+            if other_i <= 0 {
+                continue; // Underflow, so ignore
+            }
+            if goes_down(other_i as usize) {
+                // Rolled onto a position that was next to a snake leading downwards
+                lucky_spaces.insert(i);
+                break;
+            }
+        }
+    }
+    // Finally, the winning space is lucky. Every winning roll lands there, including one
+    // past the end under Overshoot::Finish
+    lucky_spaces.insert(board.size);
+    (lucky_spaces, unlucky_spaces)
+}
+
+impl Sim {
+    /// Default rules, rolling with rng
+    pub fn new(board: Board, rng: Box<dyn Roll>) -> Sim {
+        Sim::with_rules(board, Rules::default(), rng)
+    }
+
+    /// rng supplies the dice, e.g. Die::roller, or any Roll to script a game
+    pub fn with_rules(board: Board, rules: Rules, rng: Box<dyn Roll>) -> Sim {
+        // Pre-calculate (un)lucky spaces
+        let (lucky_spaces, unlucky_spaces) = calc_lucky_spaces(&board);
+        let squares = board.size + 1;
+
+        Sim {
+            board: BoardState::new(board, &rules.board_changes),
+            reroll_check: rules.die.reroll_check(),
+            rules,
+            position: 0,
+            rng,
+            lucky_spaces,
+            unlucky_spaces,
+            lucky_version: 0,
+            this_turn: TurnInProgress::default(),
+            lose_next_turn: false,
+            swap_pending: false,
+            turn_count: 0,
+            roll_count: 0,
+            climb_count: 0,
+            slide_count: 0,
+            climb_distance: 0,
+            slide_distance: 0,
+            biggest_climb: 0,
+            biggest_slide: 0,
+            longest_turn: vec![],
+            lucky_rolls: 0,
+            unlucky_rolls: 0,
+            abandoned: false,
+            landed_on: vec![0; squares],
+            ended_on: vec![0; squares],
+            route_uses: HashMap::new(),
+            special_uses: HashMap::new(),
+            turns_lost: 0,
+            swaps: 0,
+            seed: None,
+            seat: 0,
+            trace: None,
+        }
+    }
+
+    /// The board as it is now, see Rules::board_changes
+    pub fn board(&self) -> &Board {
+        self.board.board()
+    }
+
+    /// The board and how it will change
+    pub(crate) fn board_state(&self) -> &BoardState {
+        &self.board
+    }
+
+    /// Trade boards with a Game, which lends the one its players share to whoever's turn it is
+    pub(crate) fn swap_board(&mut self, board: &mut BoardState) {
+        std::mem::swap(&mut self.board, board);
+    }
+
+    /// The square the token is on, 0 before the first move
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Landed exactly on the last square
+    pub fn has_won(&self) -> bool {
+        self.position == self.board().size
+    }
+
+    /// Used all the turns allowed by max_turns
+    pub fn out_of_turns(&self) -> bool {
+        Some(self.turn_count) == self.rules.max_turns
+    }
+
+    /// Keep an Event for every roll from now on
+    pub fn record_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    /// Take turns until has_won(), or until the game is abandoned for taking too long
+    pub fn run(&mut self) {
+        while !self.has_won() {
+            if self.out_of_turns() {
+                self.abandoned = true;
+                return;
+            }
+            self.turn()
+        }
+    }
+
+    /// The next turn will be skipped, see Special::LoseTurn
+    pub fn loses_next_turn(&self) -> bool {
+        self.lose_next_turn
+    }
+
+    /// Landed on Special::SwapWithLeader this turn. Clears it, the swap is up to the caller
+    pub fn take_swap(&mut self) -> bool {
+        std::mem::take(&mut self.swap_pending)
+    }
+
+    /// Trade squares with another player, see Special::SwapWithLeader
+    pub(crate) fn swap_with(&mut self, other: &mut Sim) {
+        std::mem::swap(&mut self.position, &mut other.position);
+        self.swaps += 1;
+    }
+
+    /// Roll once, and keep rolling if the die or a special square says so. Stop immediately if we've won.
+    /// Skips the turn without rolling if the player lost it
+    pub fn turn(&mut self) {
+        if self.lose_next_turn {
+            self.lose_next_turn = false;
+            self.turn_count += 1;
+            self.turns_lost += 1;
+            self.change_board();
+            return;
+        }
+        self.start_turn();
+        while !self.has_won() {
+            if !self.turn_roll() {
+                break;
+            }
+        }
+        self.end_turn();
+    }
+
+    /// start_turn, turn_roll and end_turn are turn() a roll at a time, see trace::replay
+    pub fn start_turn(&mut self) {
+        self.turn_count += 1;
+        self.this_turn = TurnInProgress {
+            start: self.position,
+            ..TurnInProgress::default()
+        };
+        self.swap_pending = false;
+    }
+
+    /// Roll once within the turn, true if that earned another roll
+    pub fn turn_roll(&mut self) -> bool {
+        let backwards = self.this_turn.backwards;
+        let result = self.roll();
+        self.this_turn.climb += result.climb_distance;
+        self.this_turn.slide += result.slide_distance;
+        // Moving backwards doesn't earn a re-roll
+        let reroll = !result.penalty && !backwards && self.reroll_check.rerolls(&result.dice);
+        self.this_turn.rerolls += reroll as usize;
+        self.this_turn.dice.push(result.dice);
+        match result.special {
+            Some(Special::ExtraRoll) => true,
+            Some(Special::Backwards) => {
+                self.this_turn.backwards = true;
+                true
+            }
+            Some(Special::SwapWithLeader) => false,
+            _ => reroll,
+        }
+    }
+
+    /// Keep the turn's stats, and change the board if it's due
+    pub fn end_turn(&mut self) {
+        let turn = std::mem::take(&mut self.this_turn);
+        // Store turn stats
+        self.biggest_climb = max(self.biggest_climb, turn.climb);
+        self.biggest_slide = max(self.biggest_slide, turn.slide);
+        if cmp_turns(&turn.dice, &self.longest_turn) == Ordering::Greater {
+            self.longest_turn = turn.dice
+        };
+        self.change_board();
+    }
+
+    /// Make any board changes due at the end of this turn
+    fn change_board(&mut self) {
+        if self.board.end_turn() {
+            self.relayout();
+        }
+    }
+
+    /// The layout changed, so the lucky squares may have too
+    fn relayout(&mut self) {
+        (self.lucky_spaces, self.unlucky_spaces) = calc_lucky_spaces(self.board.board());
+        self.lucky_version = self.board.version();
+    }
+
+    /// Roll all the dice once and resolve the consequences
+    fn roll(&mut self) -> RollResult {
+        // Not the same as Roll::roll
+        let dice: Vec<usize> = (0..self.rules.die.count).map(|_| self.rng.roll()).collect();
+        let from = self.position;
+        let result = if std::mem::take(&mut self.this_turn.backwards) {
+            self.roll_backwards(dice.iter().sum())
+        } else if self.over_reroll_limit(&dice) {
+            self.roll_penalty()
+        } else {
+            self.roll_resolve(dice.iter().sum())
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.push(Event {
+                player: self.seat,
+                turn: self.turn_count,
+                roll: self.roll_count,
+                dice: dice.clone(),
+                from,
+                rolled: result.rolled,
+                routes: result.routes.clone(),
+                to: self.position,
+                luck: result.luck,
+                penalty: result.penalty,
+                special: result.special,
+            });
+        }
+        RollResult { dice, ..result }
+    }
+
+    /// Try to move forwards some spaces
+    fn roll_resolve(&mut self, die_value: usize) -> RollResult {
+        self.roll_count += 1;
+        let landed = self
+            .rules
+            .overshoot
+            .land(self.board().size, self.position, die_value);
+        let Some(rolled_position) = landed else {
+            // Illegal move!
+            return RollResult {
+                dice: vec![die_value],
+                climb_distance: 0,
+                slide_distance: 0,
+                rolled: self.position,
+                routes: vec![],
+                luck: Luck::Neither,
+                penalty: false,
+                special: None,
+            };
+        };
+        let mut result = self.land(rolled_position);
+        if self.has_won() {
+            return result;
+        }
+        result.special = self.board().specials.get(&self.position).copied();
+        if let Some(special) = result.special {
+            *self.special_uses.entry(self.position).or_default() += 1;
+            match special {
+                Special::LoseTurn => self.lose_next_turn = true,
+                Special::BackToStart => self.position = 0,
+                Special::SwapWithLeader => self.swap_pending = true,
+                // The rest are up to turn_roll
+                Special::ExtraRoll | Special::Backwards => {}
+            }
+        }
+        result
+    }
+
+    /// Move back by the dice, following snakes and ladders but not special squares
+    fn roll_backwards(&mut self, die_value: usize) -> RollResult {
+        self.roll_count += 1;
+        self.land(self.position.saturating_sub(die_value))
+    }
+
+    /// Put the token on the square the dice took it to, then follow any snakes and ladders from there
+    fn land(&mut self, rolled_position: usize) -> RollResult {
+        // Try to follow any routes (snake or ladder)
+        self.position = rolled_position;
+        self.landed_on[rolled_position] += 1;
+        // Another player may have changed a shared board since this one's last turn
+        if self.lucky_version != self.board.version() {
+            self.relayout();
+        }
+        // Before the routes, taking one can change the layout
+        let (unlucky, lucky) = (
+            self.is_unlucky_roll(&rolled_position),
+            self.is_lucky_roll(&rolled_position),
+        );
+        let routes = self.follow_routes();
+        self.ended_on[self.position] += 1;
+        let (climb_distance, slide_distance) = if self.position > rolled_position {
+            (self.position - rolled_position, 0)
+        } else {
+            (0, rolled_position - self.position)
+        };
+
+        // A random route is lucky or not by the way it went
+        let first_hop = match routes.first() {
+            Some(&(from, to)) if self.board().random_routes.contains_key(&from) => to.cmp(&from),
+            _ => Ordering::Equal,
+        };
+        let luck = if first_hop == Ordering::Less || unlucky {
+            // Note "unlucky" trumps lucky.
+            // If you miss a snake (lucky) and land on another (unlucky) that feels unlucky
+            self.unlucky_rolls += 1;
+            Luck::Unlucky
+        } else if first_hop == Ordering::Greater || lucky {
+            self.lucky_rolls += 1;
+            Luck::Lucky
+        } else {
+            Luck::Neither
+        };
+        RollResult {
+            dice: vec![], // Filled in by roll
+            climb_distance,
+            slide_distance,
+            rolled: rolled_position,
+            routes,
+            luck,
+            penalty: false,
+            special: None,
+        }
+    }
+
+    /// These dice would earn another re-roll, but the turn has used all it's allowed
+    fn over_reroll_limit(&self, dice: &[usize]) -> bool {
+        match self.rules.reroll_limit {
+            Some(limit) => self.this_turn.rerolls >= limit.max && self.reroll_check.rerolls(dice),
+            None => false,
+        }
+    }
+
+    /// Don't move, and undo the turn if the penalty says so
+    fn roll_penalty(&mut self) -> RollResult {
+        self.roll_count += 1;
+        let from = self.position;
+        if let Some(RerollLimit {
+            penalty: Penalty::ReturnToStart,
+            ..
+        }) = self.rules.reroll_limit
+        {
+            // The turn's climbs and slides were undone, so they don't count towards the biggest
+            self.position = self.this_turn.start;
+            self.this_turn.climb = 0;
+            self.this_turn.slide = 0;
+        }
+        RollResult {
+            dice: vec![],
+            climb_distance: 0,
+            slide_distance: 0,
+            rolled: from,
+            routes: vec![],
+            luck: Luck::Neither,
+            penalty: true,
+            special: None,
+        }
+    }
+
+    /// Follow snakes and ladders from the current position
+    /// Can follow multiple snakes/ladders, returns each one as (from, to)
+    /// A random route that doesn't fire is returned as (from, from), and ends the chain
+    fn follow_routes(&mut self) -> Vec<(usize, usize)> {
+        let mut new_position = self.position;
+        let mut followed = vec![];
+        // Board::new rejects loops, but a hand-built Board could still have one.
+        // An acyclic chain can't follow more routes than there are, so stop there.
+        let mut hops_left = self.board().routes.len() + self.board().random_routes.len();
+        while hops_left > 0 {
+            let p = if let Some(&p) = self.board().routes.get(&new_position) {
+                if self.board.took_route(new_position) {
+                    self.relayout();
+                }
+                p
+            } else if let Some(route) = self.board.board().random_routes.get(&new_position) {
+                let outcomes = route.outcomes(new_position);
+                let weights: Vec<f64> = outcomes.iter().map(|&(_, w)| w).collect();
+                outcomes[self.rng.choose(&weights)].0
+            } else {
+                break;
+            };
+            hops_left -= 1;
+            followed.push((new_position, p));
+            if p == new_position {
+                break; // Stayed put
+            }
+            *self.route_uses.entry((new_position, p)).or_default() += 1;
+            if p > new_position {
+                // ladder
+                let delta = p - new_position;
+                self.climb_count += 1;
+                self.climb_distance += delta;
+            } else {
+                // snake
+                let delta = new_position - p;
+                self.slide_count += 1;
+                self.slide_distance += delta;
+            }
+            new_position = p
+        }
+        self.position = new_position;
+        // Don't return the position to ensure sliding/climbing is an atomic action
+        followed
+    }
+
+    fn is_lucky_roll(&self, rolled_position: &usize) -> bool {
+        // We are lucky if we land on a lucky position i.e.
+        // a ladder
+        // or just missing a snake
+        // or winning
+        self.lucky_spaces.contains(rolled_position)
+    }
+
+    fn is_unlucky_roll(&self, rolled_position: &usize) -> bool {
+        // We are unlucky if we land on an unlucky position i.e.
+        // a snake
+        self.unlucky_spaces.contains(rolled_position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boards::{blank, canon_board, RandomRoute};
+    use crate::dice::{Faces, MockDie, RerollOn, Unrollable};
+    use crate::layout::BoardChange;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::{HashMap, HashSet};
+
+    /// Check can move forwards
+    #[test]
+    fn test_roll_movement() {
+        let mut sim = Sim::new(blank(20), Box::new(Unrollable {}));
+        assert_eq!(sim.position, 0, "Should start at zero");
+        sim.roll_resolve(5);
+        assert_eq!(sim.position, 5);
+        sim.roll_resolve(1);
+        assert_eq!(sim.position, 6);
+    }
+
+    /// Over-rolling should not result in movement or winning
+    #[test]
+    fn test_roll_over_rolling() {
+        let mut sim = Sim::new(blank(20), Box::new(Unrollable {}));
+        sim.roll_resolve(9999);
+        assert_eq!(sim.position, 0);
+        assert!(!sim.has_won(), "Not on the victory space, but has_won?");
+    }
+
+    #[test]
+    fn test_roll_bounce() {
+        // The bounce lands on the snake at 17
+        let board = Board::new(20, HashMap::from([(17, 4)])).unwrap();
+        let rules = Rules {
+            overshoot: Overshoot::Bounce,
+            ..Rules::default()
+        };
+        let mut sim = Sim::with_rules(board, rules, Box::new(Unrollable {}));
+        sim.position = 18;
+        let result = sim.roll_resolve(5);
+        assert_eq!(result.rolled, 17);
+        assert_eq!(sim.position, 4);
+        assert_eq!((sim.slide_count, sim.slide_distance), (1, 13));
+        assert_eq!(sim.unlucky_rolls, 1);
+        assert!(!sim.has_won());
+    }
+
+    #[test]
+    fn test_roll_finish() {
+        let rules = Rules {
+            overshoot: Overshoot::Finish,
+            ..Rules::default()
+        };
+        let mut sim = Sim::with_rules(blank(20), rules, Box::new(Unrollable {}));
+        sim.roll_resolve(9999);
+        assert!(sim.has_won());
+        assert_eq!(sim.lucky_rolls, 1);
+    }
+
+    #[test]
+    fn test_roll_winning() {
+        let mut sim = Sim::new(blank(20), Box::new(Unrollable {}));
+        sim.roll_resolve(20); // Perfect roll!
+        assert_eq!(sim.position, 20); // End of board
+        assert!(sim.has_won(), "At winning position, but not has_won?");
+
+        // test post-victory behaviour
+        sim.roll_resolve(1);
+        assert_eq!(sim.position, 20, "Moved after winning, illegal");
+    }
+
+    /// Check can generate a plausible random move
+    #[test]
+    fn test_random_roll() {
+        let max_rolls = 10; // 10 times is good enough
+        let die = Die::numbered(4);
+        let board = blank(max_rolls * die.max_face()); // Make a big enough board
+        let mut sim = Sim::new(board.clone(), die.roller(StdRng::from_entropy()));
+        for _ in 0..max_rolls {
+            let old_position = sim.position;
+            let die_value = sim.roll().dice[0];
+            println!("Rolled a {}", die_value); // Maybe useful for debugging
+            assert!(1 <= die_value, "Die value {} is < 1", die_value);
+            assert!(
+                die_value <= die.max_face(),
+                "Die value {} is > {}",
+                die_value,
+                die.max_face()
+            );
+            assert_eq!(sim.position, old_position + die_value);
+        }
+    }
+
+    #[test]
+    fn test_custom_reroll_face() {
+        let rules = Rules {
+            die: Die {
+                reroll_on: RerollOn::Face(2),
+                ..Die::numbered(4)
+            },
+            ..Rules::default()
+        };
+        let rng = Box::new(MockDie {
+            queued_results: vec![1, 4, 2, 2],
+        });
+        let mut sim = Sim::with_rules(blank(20), rules, rng);
+        sim.turn();
+        assert_eq!(sim.longest_turn, vec![vec![2], vec![2], vec![4]]);
+        sim.turn();
+        assert_eq!(sim.turn_count, 2);
+    }
+
+    #[test]
+    fn test_reroll_limit_forfeit() {
+        let rules = Rules {
+            reroll_limit: Some(RerollLimit {
+                max: 2,
+                penalty: Penalty::Forfeit,
+            }),
+            ..Rules::default()
+        };
+        let rng = Box::new(MockDie {
+            queued_results: vec![1, 6, 6, 6],
+        });
+        let mut sim = Sim::with_rules(blank(100), rules, rng);
+        sim.turn();
+        // The third 6 doesn't move
+        assert_eq!(sim.position, 12);
+        assert_eq!(sim.roll_count, 3);
+        assert_eq!(sim.longest_turn, vec![vec![6], vec![6], vec![6]]);
+        sim.turn();
+        assert_eq!((sim.turn_count, sim.position), (2, 13));
+    }
+
+    #[test]
+    fn test_reroll_limit_return_to_start() {
+        let board = Board::new(100, HashMap::from([(6, 20)])).unwrap();
+        let rules = Rules {
+            reroll_limit: Some(RerollLimit {
+                max: 2,
+                penalty: Penalty::ReturnToStart,
+            }),
+            ..Rules::default()
+        };
+        let rng = Box::new(MockDie {
+            queued_results: vec![2, 6, 6, 6],
+        });
+        let mut sim = Sim::with_rules(board, rules, rng);
+        sim.record_trace();
+        sim.turn();
+        assert_eq!(sim.position, 0);
+        // The climb happened, but the turn was undone
+        assert_eq!((sim.climb_count, sim.climb_distance), (1, 14));
+        assert_eq!(sim.biggest_climb, 0);
+        assert_eq!(sim.longest_turn.len(), 3);
+        let trace = sim.trace.as_ref().unwrap();
+        assert_eq!(
+            (trace[2].from, trace[2].to, trace[2].penalty),
+            (26, 0, true)
+        );
+        sim.turn();
+        assert_eq!(sim.position, 2);
+    }
+
+    #[test]
+    fn test_specials_in_turn() {
+        let specials = HashMap::from([
+            (2, Special::ExtraRoll),
+            (5, Special::Backwards),
+            (9, Special::BackToStart),
+        ]);
+        let board = Board::with_specials(20, HashMap::from([(3, 9)]), specials).unwrap();
+        let rules = Rules {
+            die: Die {
+                reroll_on: RerollOn::Never,
+                ..Die::default()
+            },
+            ..Rules::default()
+        };
+        // 2 rolls again, 3 to 5 rolls backwards by 6, then the next turn climbs onto 9
+        let rng = Box::new(MockDie {
+            queued_results: vec![3, 6, 3, 2],
+        });
+        let mut sim = Sim::with_rules(board, rules, rng);
+        sim.record_trace();
+        sim.turn();
+        assert_eq!(sim.position, 0);
+        assert_eq!(sim.roll_count, 3);
+        assert_eq!(sim.longest_turn, vec![vec![2], vec![3], vec![6]]);
+        sim.turn();
+        assert_eq!(sim.position, 0);
+        assert_eq!(sim.special_uses, HashMap::from([(2, 1), (5, 1), (9, 1)]));
+        let trace = sim.trace.unwrap();
+        assert_eq!(trace[1].special, Some(Special::Backwards));
+        assert_eq!((trace[2].from, trace[2].to), (5, 0));
+        assert_eq!((trace[3].routes.clone(), trace[3].to), (vec![(3, 9)], 0));
+    }
+
+    #[test]
+    fn test_two_dice() {
+        let rules = Rules {
+            die: Die {
+                count: 2,
+                reroll_on: RerollOn::AnyDouble,
+                ..Die::default()
+            },
+            ..Rules::default()
+        };
+        let rng = Box::new(MockDie {
+            queued_results: vec![6, 5, 2, 2, 4, 3],
+        });
+        let mut sim = Sim::with_rules(blank(100), rules, rng);
+        sim.turn();
+        assert_eq!(sim.position, 7);
+        sim.turn();
+        assert_eq!(sim.position, 22);
+        assert_eq!(sim.roll_count, 3);
+        assert_eq!(sim.longest_turn, vec![vec![2, 2], vec![5, 6]]);
+    }
+
+    #[test]
+    fn test_cmp_turns() {
+        // More rolls always wins, even with a lower first roll
+        assert_eq!(
+            cmp_turns(&[vec![2], vec![2], vec![1]], &[vec![5]]),
+            Ordering::Greater
+        );
+        assert_eq!(
+            cmp_turns(&[vec![6], vec![4]], &[vec![6], vec![3]]),
+            Ordering::Greater
+        );
+        assert_eq!(
+            cmp_turns(&[vec![6, 6, 6, 5]], &[vec![6, 6, 6, 6, 1]]),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_weighted_die() {
+        // The 1 can never come up
+        let die = Die {
+            faces: Faces::Values(vec![1, 5]),
+            count: 1,
+            weights: Some(vec![0.0, 1.0]),
+            reroll_on: RerollOn::Never,
+        };
+        assert!(die.validate().is_ok());
+        assert_eq!(die.values(), vec![5]);
+        let mut sim = Sim::new(blank(20), die.roller(StdRng::from_entropy()));
+        for _ in 0..4 {
+            assert_eq!(sim.roll().dice, vec![5]);
+        }
+        assert!(sim.has_won());
+    }
+
+    #[test]
+    fn test_roll_outcomes() {
+        let chance = |die: &Die, reroll: bool| -> f64 {
+            let outcomes = die.roll_outcomes();
+            outcomes.iter().filter(|o| o.2 == reroll).map(|o| o.1).sum()
+        };
+        let two = Die {
+            count: 2,
+            reroll_on: RerollOn::AnyDouble,
+            ..Die::default()
+        };
+        assert!((chance(&two, true) - 1.0 / 6.0).abs() < 1e-12);
+        let three = Die { count: 3, ..two };
+        assert!((chance(&three, true) - (1.0 - 120.0 / 216.0)).abs() < 1e-12);
+        // Totals, not every combination, so lots of dice are cheap
+        let many = Die {
+            count: 40,
+            ..Die::default()
+        };
+        assert_eq!(many.values(), (40..=240).collect::<Vec<usize>>());
+        assert!((chance(&many, true) - (1.0f64 / 6.0).powi(40)).abs() < 1e-40);
+        assert!((chance(&many, false) + chance(&many, true) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_blank_face() {
+        let rules = Rules {
+            die: Die {
+                faces: Faces::Values(vec![0, 1, 1, 2, 3, 5]),
+                ..Die::default()
+            },
+            ..Rules::default()
+        };
+        let rng = Box::new(MockDie {
+            queued_results: vec![1, 5, 0],
+        });
+        let mut sim = Sim::with_rules(blank(20), rules, rng);
+        sim.turn();
+        assert_eq!(sim.position, 0);
+        sim.turn();
+        assert_eq!(sim.longest_turn, vec![vec![5], vec![1]]); // 5 is the max face
+        assert_eq!(sim.position, 6);
+    }
+
+    #[test]
+    fn test_lucky_spaces() {
+        // If rules for luck changes, should replace this with checking rolls.
+        let board = Board::new(20, HashMap::from([(5, 8), (14, 2)])).unwrap();
+        let sim = Sim::new(board, Box::new(Unrollable {}));
+        assert_eq!(
+            sim.lucky_spaces,
+            HashSet::from([
+                5, // Ladders up
+                12, 13, 15, 16, // near a snake
+                20  // Winning square
+            ])
+        );
+        assert_eq!(sim.unlucky_spaces, HashSet::from([14]));
+    }
+
+    #[test]
+    fn test_canon_board_speedrun() {
+        // More fun than useful!
+        // Can probably be deleted if the canon_board changes
+        let b = canon_board();
+        let rng = Box::new(MockDie {
+            queued_results: vec![2, 6, 5, 1, 2, 6, 4],
+        });
+        let mut sim = Sim::new(b, rng);
+        sim.run();
+        assert_eq!(sim.roll_count, 7);
+        assert_eq!(sim.turn_count, 5);
+        assert_eq!(sim.climb_count, 4);
+        assert_eq!(sim.slide_count, 0);
+        assert_eq!(sim.climb_distance, 74);
+        assert_eq!(sim.slide_distance, 0);
+        assert_eq!(sim.biggest_climb, 21);
+        assert_eq!(sim.biggest_slide, 0);
+        assert_eq!(sim.lucky_rolls, 6);
+        assert_eq!(sim.unlucky_rolls, 0);
+        assert!(sim.has_won());
+    }
+
+    #[test]
+    fn test_max_turns() {
+        // Can't win a blank board by rolling 1s, so give up
+        let rules = Rules {
+            max_turns: Some(3),
+            ..Rules::default()
+        };
+        let rng = Box::new(MockDie {
+            queued_results: vec![1, 1, 1, 1],
+        });
+        let mut sim = Sim::with_rules(blank(20), rules, rng);
+        sim.run();
+        assert_eq!(sim.turn_count, 3);
+        assert!(sim.abandoned);
+        assert!(!sim.has_won());
+    }
+
+    #[test]
+    fn test_follow_routes_loop() {
+        // Skip Board::new validation, the loop must not hang the sim
+        let b = Board {
+            size: 100,
+            routes: HashMap::from([(4, 25), (25, 4)]),
+            specials: HashMap::new(),
+            random_routes: HashMap::new(),
+        };
+        let mut sim = Sim::new(b, Box::new(Unrollable {}));
+        sim.roll_resolve(4);
+        assert_eq!(sim.climb_count + sim.slide_count, 2);
+    }
+
+    #[test]
+    fn test_random_routes() {
+        // Landing on 6 goes to 1 or 15, or stays put half the time
+        let random = RandomRoute {
+            chance: 0.5,
+            to: vec![(1, 1.0), (15, 1.0)],
+        };
+        let board = blank(30)
+            .with_random_routes(HashMap::from([(6, random)]))
+            .unwrap();
+        // Only choices are queued, as the index of the outcome
+        let rng = Box::new(MockDie {
+            queued_results: vec![2, 1, 0],
+        });
+        let mut sim = Sim::new(board, rng);
+        assert!(sim.lucky_spaces.contains(&4) && !sim.lucky_spaces.contains(&6));
+        assert!(sim.unlucky_spaces.is_empty());
+        let result = sim.roll_resolve(6);
+        assert_eq!((result.routes, result.luck), (vec![(6, 1)], Luck::Unlucky));
+        assert_eq!(sim.slide_distance, 5);
+        let result = sim.roll_resolve(5);
+        assert_eq!((result.routes, result.luck), (vec![(6, 15)], Luck::Lucky));
+        sim.position = 0;
+        let result = sim.roll_resolve(6);
+        assert_eq!((result.routes, result.luck), (vec![(6, 6)], Luck::Neither));
+        assert_eq!(sim.position, 6);
+        assert_eq!(sim.route_uses, HashMap::from([((6, 1), 1), ((6, 15), 1)]));
+    }
+
+    #[test]
+    fn test_ladder_breaks() {
+        let rules = Rules {
+            board_changes: vec![BoardChange::Break { from: 3, after: 2 }],
+            ..Rules::default()
+        };
+        let board = Board::new(20, HashMap::from([(3, 8)])).unwrap();
+        let mut sim = Sim::with_rules(board, rules, Box::new(Unrollable {}));
+        assert_eq!(sim.roll_resolve(3).luck, Luck::Lucky);
+        sim.position = 0;
+        // Still lucky, it only broke on the way up
+        assert_eq!(sim.roll_resolve(3).luck, Luck::Lucky);
+        assert!(!sim.lucky_spaces.contains(&3));
+        sim.position = 0;
+        assert_eq!(sim.roll_resolve(3).luck, Luck::Neither);
+        assert_eq!((sim.position, sim.climb_count), (3, 2));
+    }
+
+    #[test]
+    fn test_snake_moves() {
+        let rules = Rules {
+            board_changes: vec![BoardChange::Move {
+                from: 15,
+                every: 1,
+                positions: vec![(16, 2)],
+            }],
+            ..Rules::default()
+        };
+        let board = Board::new(20, HashMap::from([(15, 5)])).unwrap();
+        let rng = Box::new(MockDie {
+            queued_results: vec![1],
+        });
+        let mut sim = Sim::with_rules(board, rules, rng);
+        assert!(sim.lucky_spaces.contains(&13) && !sim.lucky_spaces.contains(&18));
+        sim.turn();
+        assert_eq!(sim.board().routes, HashMap::from([(16, 2)]));
+        assert!(!sim.lucky_spaces.contains(&13) && sim.lucky_spaces.contains(&18));
+        assert_eq!(sim.unlucky_spaces, HashSet::from([16]));
+    }
+
+    #[test]
+    fn test_chained_slides() {
+        // Take one step forwards and fall down a chain of snakes
+        // then re-roll and go down another snake
+        let b = Board::new(100, HashMap::from([(99, 60), (60, 30), (30, 2), (5, 1)])).unwrap();
+        let rng = Box::new(MockDie {
+            queued_results: vec![3, 6],
+        });
+        let mut sim = Sim::new(b, rng);
+        sim.position = 93; // Override position
+        sim.turn();
+        assert_eq!(sim.roll_count, 2);
+        assert_eq!(sim.turn_count, 1);
+        assert_eq!(sim.climb_count, 0);
+        assert_eq!(sim.slide_count, 4);
+        assert_eq!(sim.climb_distance, 0);
+        assert_eq!(sim.slide_distance, 101);
+        assert_eq!(sim.biggest_climb, 0);
+        assert_eq!(sim.biggest_slide, 101);
+        assert_eq!(sim.lucky_rolls, 0);
+        assert_eq!(sim.unlucky_rolls, 2);
+        assert_eq!(sim.longest_turn, vec![vec![6], vec![3]]);
+        assert!(!sim.has_won());
+        assert_eq!((sim.landed_on[99], sim.ended_on[99]), (1, 0));
+        assert_eq!((sim.landed_on[5], sim.ended_on[1]), (1, 1));
+        assert_eq!(sim.ended_on[2], 1);
+        assert_eq!(
+            sim.route_uses,
+            HashMap::from([((99, 60), 1), ((60, 30), 1), ((30, 2), 1), ((5, 1), 1)])
+        );
+    }
+}
@@ -0,0 +1,496 @@
+//! Stats gathered over a batch of games.
+
+use crate::batch::{BatchError, ExtremeSeeds, MultiSimResult};
+use crate::boards::Special;
+use crate::game::Game;
+use crate::sim::{cmp_turns, Sim};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// Histograms have at most this many buckets
+const HISTOGRAM_BUCKETS: usize = 10;
+
+/// The shape of one stat across a batch, beyond min/average/max
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Distribution {
+    /// Standard deviation
+    pub std_dev: f64,
+    /// Half the games are at or below this
+    pub median: usize,
+    /// 90% of the games are at or below this
+    pub p90: usize,
+    /// 95% of the games are at or below this
+    pub p95: usize,
+    /// 99% of the games are at or below this
+    pub p99: usize,
+    /// Games counted in even ranges, lowest first
+    pub histogram: Vec<Bucket>,
+}
+
+/// How many games had a stat between from and to, inclusive
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Bucket {
+    /// Lowest value in the bucket
+    pub from: usize,
+    /// Highest value in the bucket
+    pub to: usize,
+    /// Games in the bucket
+    pub count: usize,
+}
+
+/// Which squares and routes players actually use, as the chance of it happening at least once in a game
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Heatmap {
+    /// By square, 0 to size, landed on by a roll before any snake or ladder
+    pub landed_on: Vec<f64>,
+    /// By square, where a roll finished after snakes and ladders
+    pub ended_on: Vec<f64>,
+    /// Every snake and ladder, in order of where they start
+    pub routes: Vec<RouteUse>,
+}
+
+/// How likely a player is to take one snake or ladder
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RouteUse {
+    /// Where it starts
+    pub from: usize,
+    /// Where it ends
+    pub to: usize,
+    /// Of being taken at least once in a game
+    pub chance: f64,
+}
+
+/// Add one to each total whose count is above zero, growing totals to fit
+fn count_nonzero(totals: &mut Vec<usize>, counts: &[usize]) {
+    if totals.len() < counts.len() {
+        totals.resize(counts.len(), 0);
+    }
+    for (total, &count) in totals.iter_mut().zip(counts) {
+        *total += (count > 0) as usize;
+    }
+}
+
+/// Element-wise sum, growing totals to fit
+fn add_totals(totals: &mut Vec<usize>, later: &[usize]) {
+    if totals.len() < later.len() {
+        totals.resize(later.len(), 0);
+    }
+    for (total, &count) in totals.iter_mut().zip(later) {
+        *total += count;
+    }
+}
+
+/// Running min/average/max (and variance) of one stat, one game at a time.
+/// Also remembers the seeds of the games that set the min and max.
+/// Keeps a count per value for percentiles, so memory grows with the max, not the number of games
+#[derive(Debug, Clone, Default)]
+pub struct Tally {
+    count: usize,
+    sum: u128,
+    sum_squares: u128,
+    min: Option<(usize, Option<u64>)>,
+    max: Option<(usize, Option<u64>)>,
+    value_counts: Vec<usize>, // value_counts[v] is how many times v was added
+}
+
+impl Tally {
+    /// Count one game's value, seed being the game that gave it
+    pub fn add(&mut self, value: usize, seed: Option<u64>) {
+        self.count += 1;
+        self.sum += value as u128;
+        self.sum_squares += (value as u128) * (value as u128);
+        if self.value_counts.len() <= value {
+            self.value_counts.resize(value + 1, 0);
+        }
+        self.value_counts[value] += 1;
+        // Ties go to the first min and the last max
+        if self.min.is_none_or(|(min, _)| value < min) {
+            self.min = Some((value, seed));
+        }
+        if self.max.is_none_or(|(max, _)| value >= max) {
+            self.max = Some((value, seed));
+        }
+    }
+
+    /// Fold in the tally of the games that came after this one's
+    pub fn merge(&mut self, later: &Tally) {
+        self.count += later.count;
+        self.sum += later.sum;
+        self.sum_squares += later.sum_squares;
+        if self.value_counts.len() < later.value_counts.len() {
+            self.value_counts.resize(later.value_counts.len(), 0);
+        }
+        for (count, later_count) in self.value_counts.iter_mut().zip(&later.value_counts) {
+            *count += later_count;
+        }
+        if let Some((min, seed)) = later.min {
+            if self.min.is_none_or(|(m, _)| min < m) {
+                self.min = Some((min, seed));
+            }
+        }
+        if let Some((max, seed)) = later.max {
+            if self.max.is_none_or(|(m, _)| max >= m) {
+                self.max = Some((max, seed));
+            }
+        }
+    }
+
+    /// None if nothing was added
+    pub fn min_avg_max(&self) -> Option<(usize, f64, usize)> {
+        Some((self.min?.0, self.mean()?, self.max?.0))
+    }
+
+    /// None if nothing was added
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.sum as f64 / self.count as f64)
+        }
+    }
+
+    /// Population variance
+    pub fn variance(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        Some((self.sum_squares as f64 / self.count as f64 - mean * mean).max(0.0))
+    }
+
+    /// Smallest value with at least a fraction p of the values at or below it, p in (0, 1]
+    pub fn percentile(&self, p: f64) -> Option<usize> {
+        let rank = ((p * self.count as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (value, &count) in self.value_counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Up to HISTOGRAM_BUCKETS equal width buckets from min to max
+    pub fn histogram(&self) -> Vec<Bucket> {
+        let (min, max) = match (self.min, self.max) {
+            (Some((min, _)), Some((max, _))) => (min, max),
+            _ => return vec![],
+        };
+        let width = (max - min + 1).div_ceil(HISTOGRAM_BUCKETS);
+        (min..=max)
+            .step_by(width)
+            .map(|from| {
+                let to = max.min(from + width - 1);
+                Bucket {
+                    from,
+                    to,
+                    count: self.value_counts[from..=to].iter().sum(),
+                }
+            })
+            .collect()
+    }
+
+    /// None if nothing was added
+    pub fn distribution(&self) -> Option<Distribution> {
+        Some(Distribution {
+            std_dev: self.variance()?.sqrt(),
+            median: self.percentile(0.5)?,
+            p90: self.percentile(0.9)?,
+            p95: self.percentile(0.95)?,
+            p99: self.percentile(0.99)?,
+            histogram: self.histogram(),
+        })
+    }
+
+    /// Seed of the game that set the min, if it had one
+    pub fn min_seed(&self) -> Option<u64> {
+        self.min?.1
+    }
+
+    /// Seed of the game that set the max, if it had one
+    pub fn max_seed(&self) -> Option<u64> {
+        self.max?.1
+    }
+}
+
+/// Everything MultiSimResult needs, folded in one game at a time so a batch runs in constant memory
+#[derive(Debug, Clone, Default)]
+pub struct BatchStats {
+    games: usize,
+    abandoned_games: usize,
+    seat_wins: Vec<usize>,
+    // Per player
+    rolls: Tally,
+    climb: Tally,
+    slide: Tally,
+    lucky_rolls: Tally,
+    unlucky_rolls: Tally,
+    biggest_turn_climb: Tally,
+    biggest_turn_slide: Tally,
+    longest_turn: Option<(Vec<Vec<usize>>, Option<u64>)>,
+    // Players who used each square and route at least once
+    players: usize,
+    landed_on: Vec<usize>,
+    ended_on: Vec<usize>,
+    route_uses: BTreeMap<(usize, usize), usize>, // By (from, to), so unused routes show up too
+    special_uses: BTreeMap<Special, usize>, // Total times each kind took effect, across every player
+}
+
+impl BatchStats {
+    /// Count every player in the game
+    pub fn add_game(&mut self, game: &Game) {
+        self.add(&game.players, game.winner, game.abandoned());
+    }
+
+    /// A lone Sim counts as a single player game
+    pub fn add_sim(&mut self, sim: &Sim) {
+        let winner = if sim.has_won() { Some(0) } else { None };
+        self.add(std::slice::from_ref(sim), winner, sim.abandoned);
+    }
+
+    fn add(&mut self, players: &[Sim], winner: Option<usize>, abandoned: bool) {
+        self.games += 1;
+        if abandoned {
+            self.abandoned_games += 1;
+        }
+        if self.seat_wins.len() < players.len() {
+            self.seat_wins.resize(players.len(), 0);
+        }
+        // Only a won game says how many rolls it takes to win
+        if let Some(seat) = winner {
+            self.seat_wins[seat] += 1;
+            self.rolls.add(players[seat].roll_count, players[seat].seed);
+        }
+        for sim in players {
+            self.climb.add(sim.climb_distance, sim.seed);
+            self.slide.add(sim.slide_distance, sim.seed);
+            self.lucky_rolls.add(sim.lucky_rolls, sim.seed);
+            self.unlucky_rolls.add(sim.unlucky_rolls, sim.seed);
+            self.biggest_turn_climb.add(sim.biggest_climb, sim.seed);
+            self.biggest_turn_slide.add(sim.biggest_slide, sim.seed);
+            let longer = match &self.longest_turn {
+                Some((turn, _)) => cmp_turns(&sim.longest_turn, turn) != Ordering::Less,
+                None => true,
+            };
+            if longer {
+                self.longest_turn = Some((sim.longest_turn.clone(), sim.seed));
+            }
+            self.players += 1;
+            count_nonzero(&mut self.landed_on, &sim.landed_on);
+            count_nonzero(&mut self.ended_on, &sim.ended_on);
+            // Routes that were taken could have moved or broken since
+            let mut routes = sim.board().all_routes();
+            routes.extend(sim.route_uses.keys());
+            routes.sort_unstable();
+            routes.dedup();
+            for (from, to) in routes {
+                let used = sim.route_uses.get(&(from, to)).is_some_and(|&n| n > 0);
+                *self.route_uses.entry((from, to)).or_default() += used as usize;
+            }
+            for (square, &special) in sim.board().specials.iter() {
+                let uses = sim.special_uses.get(square).copied().unwrap_or(0);
+                *self.special_uses.entry(special).or_default() += uses;
+            }
+        }
+    }
+
+    /// Fold in the stats of the games that came after this one's
+    pub fn merge(&mut self, later: &BatchStats) {
+        self.games += later.games;
+        self.abandoned_games += later.abandoned_games;
+        if self.seat_wins.len() < later.seat_wins.len() {
+            self.seat_wins.resize(later.seat_wins.len(), 0);
+        }
+        for (wins, later_wins) in self.seat_wins.iter_mut().zip(later.seat_wins.iter()) {
+            *wins += later_wins;
+        }
+        self.rolls.merge(&later.rolls);
+        self.climb.merge(&later.climb);
+        self.slide.merge(&later.slide);
+        self.lucky_rolls.merge(&later.lucky_rolls);
+        self.unlucky_rolls.merge(&later.unlucky_rolls);
+        self.biggest_turn_climb.merge(&later.biggest_turn_climb);
+        self.biggest_turn_slide.merge(&later.biggest_turn_slide);
+        if let Some((later_turn, seed)) = &later.longest_turn {
+            let longer = match &self.longest_turn {
+                Some((turn, _)) => cmp_turns(later_turn, turn) != Ordering::Less,
+                None => true,
+            };
+            if longer {
+                self.longest_turn = Some((later_turn.clone(), *seed));
+            }
+        }
+        self.players += later.players;
+        add_totals(&mut self.landed_on, &later.landed_on);
+        add_totals(&mut self.ended_on, &later.ended_on);
+        for (&route, &uses) in later.route_uses.iter() {
+            *self.route_uses.entry(route).or_default() += uses;
+        }
+        for (&special, &uses) in later.special_uses.iter() {
+            *self.special_uses.entry(special).or_default() += uses;
+        }
+    }
+
+    /// Where the players so far went, as chances
+    pub fn heatmap(&self) -> Heatmap {
+        let chance = |count: usize| count as f64 / self.players as f64;
+        Heatmap {
+            landed_on: self.landed_on.iter().map(|&n| chance(n)).collect(),
+            ended_on: self.ended_on.iter().map(|&n| chance(n)).collect(),
+            routes: self
+                .route_uses
+                .iter()
+                .map(|(&(from, to), &n)| RouteUse {
+                    from,
+                    to,
+                    chance: chance(n),
+                })
+                .collect(),
+        }
+    }
+
+    /// An error if no games were added
+    pub fn result(&self) -> Result<MultiSimResult, BatchError> {
+        if self.games == 0 {
+            return Err(BatchError::NoGames);
+        }
+        // Every tally has a value once there's a game
+        // Unless every game was abandoned
+        let rolls = self.rolls.min_avg_max();
+        let (min_climb, avg_climb, max_climb) = self.climb.min_avg_max().unwrap();
+        let (min_slide, avg_slide, max_slide) = self.slide.min_avg_max().unwrap();
+        let (min_lucky_rolls, avg_lucky_rolls, max_lucky_rolls) =
+            self.lucky_rolls.min_avg_max().unwrap();
+        let (min_unlucky_rolls, avg_unlucky_rolls, max_unlucky_rolls) =
+            self.unlucky_rolls.min_avg_max().unwrap();
+        let (longest_turn, longest_turn_seed) = self.longest_turn.clone().unwrap();
+        Ok(MultiSimResult {
+            min_rolls: rolls.map(|r| r.0),
+            avg_rolls: rolls.map(|r| r.1),
+            max_rolls: rolls.map(|r| r.2),
+            min_climb,
+            avg_climb,
+            max_climb,
+            min_slide,
+            avg_slide,
+            max_slide,
+            biggest_turn_climb: self.biggest_turn_climb.min_avg_max().unwrap().2,
+            biggest_turn_slide: self.biggest_turn_slide.min_avg_max().unwrap().2,
+            longest_turn,
+            min_lucky_rolls,
+            avg_lucky_rolls,
+            max_lucky_rolls,
+            min_unlucky_rolls,
+            avg_unlucky_rolls,
+            max_unlucky_rolls,
+            rolls_distribution: self.rolls.distribution(),
+            climb_distribution: self.climb.distribution().unwrap(),
+            slide_distribution: self.slide.distribution().unwrap(),
+            lucky_rolls_distribution: self.lucky_rolls.distribution().unwrap(),
+            unlucky_rolls_distribution: self.unlucky_rolls.distribution().unwrap(),
+            abandoned_games: self.abandoned_games,
+            seat_win_rates: self
+                .seat_wins
+                .iter()
+                .map(|&w| w as f64 / self.games as f64)
+                .collect(),
+            special_uses: self
+                .special_uses
+                .iter()
+                .map(|(&special, &uses)| (special, uses as f64 / self.players as f64))
+                .collect(),
+            heatmap: self.heatmap(),
+            seed: None,
+            extreme_seeds: ExtremeSeeds {
+                min_rolls: self.rolls.min_seed(),
+                max_rolls: self.rolls.max_seed(),
+                min_climb: self.climb.min_seed(),
+                max_climb: self.climb.max_seed(),
+                min_slide: self.slide.min_seed(),
+                max_slide: self.slide.max_seed(),
+                biggest_turn_climb: self.biggest_turn_climb.max_seed(),
+                biggest_turn_slide: self.biggest_turn_slide.max_seed(),
+                longest_turn: longest_turn_seed,
+                min_lucky_rolls: self.lucky_rolls.min_seed(),
+                max_lucky_rolls: self.lucky_rolls.max_seed(),
+                min_unlucky_rolls: self.unlucky_rolls.min_seed(),
+                max_unlucky_rolls: self.unlucky_rolls.max_seed(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tally(values: &[usize]) -> Tally {
+        let mut tally = Tally::default();
+        for (i, &v) in values.iter().enumerate() {
+            tally.add(v, Some(i as u64));
+        }
+        tally
+    }
+
+    #[test]
+    fn test_tally_variance() {
+        let t = tally(&[2, 4, 4, 4, 5, 5, 7, 9]);
+        assert_eq!(t.mean(), Some(5.0));
+        assert_eq!(t.variance(), Some(4.0));
+    }
+
+    #[test]
+    fn test_tally_percentiles() {
+        let t = tally(&(1..=100).collect::<Vec<usize>>());
+        assert_eq!(t.percentile(0.5), Some(50));
+        assert_eq!(t.percentile(0.9), Some(90));
+        assert_eq!(t.percentile(0.99), Some(99));
+        assert_eq!(t.percentile(1.0), Some(100));
+        let t = tally(&[7]);
+        assert_eq!(t.percentile(0.01), Some(7));
+        assert_eq!(Tally::default().percentile(0.5), None);
+    }
+
+    #[test]
+    fn test_tally_histogram() {
+        let t = tally(&(5..=30).collect::<Vec<usize>>());
+        let histogram = t.histogram();
+        assert_eq!(histogram.len(), 9); // 26 values, 3 per bucket
+        assert_eq!(
+            histogram[0],
+            Bucket {
+                from: 5,
+                to: 7,
+                count: 3
+            }
+        );
+        assert_eq!(
+            histogram[8],
+            Bucket {
+                from: 29,
+                to: 30,
+                count: 2
+            }
+        );
+        assert_eq!(histogram.iter().map(|b| b.count).sum::<usize>(), 26);
+    }
+
+    #[test]
+    fn test_tally_seeds() {
+        // First min, last max
+        let t = tally(&[3, 1, 8, 1, 8]);
+        assert_eq!(t.min_seed(), Some(1));
+        assert_eq!(t.max_seed(), Some(4));
+    }
+
+    #[test]
+    fn test_tally_merge() {
+        let mut t = tally(&[3, 1, 8]);
+        let mut later = Tally::default();
+        later.add(1, Some(10));
+        later.add(8, Some(11));
+        t.merge(&later);
+        assert_eq!(t.min_avg_max(), Some((1, 21.0 / 5.0, 8)));
+        assert_eq!(t.min_seed(), Some(1));
+        assert_eq!(t.max_seed(), Some(11));
+    }
+}
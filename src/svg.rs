@@ -0,0 +1,199 @@
+//! Drawing the board and heatmaps as SVG images.
+
+use crate::boards::Board;
+use crate::render::{grid_position, grid_width, heat_scale, numbered_routes, Overlay};
+use crate::sim::calc_lucky_spaces;
+use crate::stats::Heatmap;
+
+/// Side of a square, in pixels
+const CELL: f64 = 60.0;
+/// Space around the grid, in pixels
+const MARGIN: f64 = 10.0;
+/// Gap between the rails of a ladder, in pixels
+const LADDER_WIDTH: f64 = 10.0;
+/// Distance between the rungs of a ladder, in pixels
+const RUNG_GAP: f64 = 14.0;
+
+const SNAKE_COLOUR: &str = "#2e8b57";
+const LADDER_COLOUR: &str = "#8b5a2b";
+
+/// Centre of a square in the picture
+fn centre(board: &Board, square: usize) -> (f64, f64) {
+    let rows = board.size.div_ceil(grid_width(board.size));
+    let (col, row) = grid_position(board.size, square);
+    (
+        MARGIN + (col as f64 + 0.5) * CELL,
+        MARGIN + ((rows - 1 - row) as f64 + 0.5) * CELL,
+    )
+}
+
+/// Background of a square: a checkerboard, or the overlay if there is one
+fn fill(square: usize, luck: Option<(bool, bool)>, heat: Option<f64>) -> String {
+    match (luck, heat) {
+        (Some((_, true)), _) => "#f4a6a6".to_string(), // Unlucky trumps lucky, like in Sim
+        (Some((true, _)), _) => "#a6e3a6".to_string(),
+        (_, Some(heat)) => {
+            // White to red
+            let other = (255.0 * (1.0 - heat)).round() as u8;
+            format!("#ff{:02x}{:02x}", other, other)
+        }
+        _ if square.is_multiple_of(2) => "#f4f1e8".to_string(),
+        _ => "#e8e2d0".to_string(),
+    }
+}
+
+/// A gently curved snake from head to tail, with a dot for the head
+fn snake(from: (f64, f64), to: (f64, f64)) -> String {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    // Bend the middle sideways by a fifth of the length
+    let control = (
+        (from.0 + to.0) / 2.0 - dy * 0.2,
+        (from.1 + to.1) / 2.0 + dx * 0.2,
+    );
+    format!(
+        "<path d=\"M {:.1} {:.1} Q {:.1} {:.1} {:.1} {:.1}\" fill=\"none\" stroke=\"{}\" stroke-width=\"6\" stroke-linecap=\"round\" opacity=\"0.8\"/>\n\
+         <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"7\" fill=\"{}\"/>\n",
+        from.0, from.1, control.0, control.1, to.0, to.1, SNAKE_COLOUR, from.0, from.1, SNAKE_COLOUR
+    )
+}
+
+/// Two rails from foot to top, joined by rungs
+fn ladder(from: (f64, f64), to: (f64, f64)) -> String {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    // Half the ladder's width, at right angles to it
+    let (ox, oy) = (
+        -dy / length * LADDER_WIDTH / 2.0,
+        dx / length * LADDER_WIDTH / 2.0,
+    );
+    let line = |x1: f64, y1: f64, x2: f64, y2: f64| {
+        format!(
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"3\"/>\n",
+            x1, y1, x2, y2, LADDER_COLOUR
+        )
+    };
+    let mut text = line(from.0 + ox, from.1 + oy, to.0 + ox, to.1 + oy);
+    text += &line(from.0 - ox, from.1 - oy, to.0 - ox, to.1 - oy);
+    let rungs = (length / RUNG_GAP).floor() as usize;
+    for i in 1..rungs {
+        let t = i as f64 / rungs as f64;
+        let (x, y) = (from.0 + dx * t, from.1 + dy * t);
+        text += &line(x + ox, y + oy, x - ox, y - oy);
+    }
+    text
+}
+
+/// A standalone SVG picture of the board, square 1 bottom left like render_board.
+/// Overlay::Luck colours lucky squares green and unlucky ones red,
+/// Overlay::Heatmap colours squares redder the more often they're landed on, if there's a heatmap
+pub fn render_svg(board: &Board, overlay: Overlay, heatmap: Option<&Heatmap>) -> String {
+    let width = grid_width(board.size);
+    let rows = board.size.div_ceil(width);
+    let (pixels_wide, pixels_high) = (
+        width as f64 * CELL + 2.0 * MARGIN,
+        rows as f64 * CELL + 2.0 * MARGIN,
+    );
+    let luck = match overlay {
+        Overlay::Luck => Some(calc_lucky_spaces(board)),
+        _ => None,
+    };
+    let heat = match (overlay, heatmap) {
+        (Overlay::Heatmap, Some(heatmap)) => Some(heat_scale(board, heatmap)),
+        _ => None,
+    };
+
+    let mut text = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" font-family=\"sans-serif\">\n",
+        pixels_wide, pixels_high, pixels_wide, pixels_high
+    );
+    text += &format!(
+        "<rect width=\"{}\" height=\"{}\" fill=\"white\"/>\n",
+        pixels_wide, pixels_high
+    );
+    for square in 1..=board.size {
+        let (x, y) = centre(board, square);
+        let square_luck = luck
+            .as_ref()
+            .map(|(lucky, unlucky)| (lucky.contains(&square), unlucky.contains(&square)));
+        let square_heat = heat.as_ref().map(|heat| heat[square]);
+        text += &format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"#999\"/>\n",
+            x - CELL / 2.0,
+            y - CELL / 2.0,
+            CELL,
+            CELL,
+            fill(square, square_luck, square_heat)
+        );
+        text += &format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"12\" fill=\"#333\">{}</text>\n",
+            x - CELL / 2.0 + 4.0,
+            y - CELL / 2.0 + 14.0,
+            square
+        );
+    }
+    // Routes go over the squares, ladders first so snakes are on top
+    let (snakes, ladders) = numbered_routes(board);
+    for &(from, to) in ladders.iter() {
+        text += &ladder(centre(board, from), centre(board, to));
+    }
+    for &(from, to) in snakes.iter() {
+        // A snake down to square 0 leaves the board, aim it at the bottom left corner
+        let tail = match to {
+            0 => (MARGIN, pixels_high - MARGIN),
+            _ => centre(board, to),
+        };
+        text += &snake(centre(board, from), tail);
+    }
+    text += "</svg>\n";
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boards::canon_board;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_svg_canon_board() {
+        let svg = render_svg(&canon_board(), Overlay::None, None);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"620\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), 101); // Background and 100 squares
+        assert_eq!(svg.matches("<path ").count(), 8);
+        assert_eq!(svg.matches(">100</text>").count(), 1);
+    }
+
+    #[test]
+    fn test_svg_positions() {
+        let board = Board::new(8, HashMap::new()).unwrap();
+        assert_eq!(centre(&board, 1), (40.0, 160.0));
+        assert_eq!(centre(&board, 6), (40.0, 100.0));
+        assert_eq!(centre(&board, 7), (40.0, 40.0));
+    }
+
+    #[test]
+    fn test_svg_ladder_rungs() {
+        // Straight up two squares, 120 pixels
+        let text = ladder((40.0, 160.0), (40.0, 40.0));
+        assert_eq!(text.matches("<line ").count(), 2 + 7);
+        assert!(text.starts_with("<line x1=\"45.0\" y1=\"160.0\" x2=\"45.0\" y2=\"40.0\""));
+    }
+
+    #[test]
+    fn test_svg_overlays() {
+        let board = Board::new(4, HashMap::from([(3, 1)])).unwrap();
+        let svg = render_svg(&board, Overlay::Luck, None);
+        assert_eq!(svg.matches("fill=\"#f4a6a6\"").count(), 1);
+        assert_eq!(svg.matches("fill=\"#a6e3a6\"").count(), 3);
+        let heatmap = Heatmap {
+            landed_on: vec![0.0, 0.5, 0.25, 0.0, 1.0],
+            ended_on: vec![0.0, 0.5, 0.25, 0.0, 1.0],
+            routes: vec![],
+        };
+        let svg = render_svg(&board, Overlay::Heatmap, Some(&heatmap));
+        assert_eq!(svg.matches("fill=\"#ff0000\"").count(), 2); // Busiest square and the finish
+        assert_eq!(svg.matches("fill=\"#ff8080\"").count(), 1);
+        assert_eq!(svg.matches("fill=\"#ffffff\"").count(), 1);
+    }
+}
@@ -0,0 +1,422 @@
+//! Turn by turn records of a game, and replaying them.
+
+use crate::boards::{Board, Special};
+use crate::dice::MockDie;
+use crate::game::{swap_with_leader, Game};
+use crate::sim::{Rules, Sim};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How a roll counts towards lucky_rolls and unlucky_rolls
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Luck {
+    /// Landed on a lucky square, see sim::calc_lucky_spaces
+    Lucky,
+    /// Landed on an unlucky square
+    Unlucky,
+    /// Landed on any other square
+    Neither,
+}
+
+/// Everything that happened in one roll, see Sim::record_trace
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    /// Seat in the game
+    pub player: usize,
+    /// The player's turn, from 1
+    pub turn: usize,
+    /// The player's roll, from 1
+    pub roll: usize,
+    /// What each die showed
+    pub dice: Vec<usize>,
+    /// Square before the roll
+    pub from: usize,
+    /// Square the dice moved to, the same as from if they overshot
+    pub rolled: usize,
+    /// Each snake or ladder followed from there, in order. (from, from) if a random one didn't fire
+    pub routes: Vec<(usize, usize)>,
+    /// Square after the roll
+    pub to: usize,
+    /// Whether the square rolled onto was lucky
+    pub luck: Luck,
+    /// Went over the reroll limit, see sim::RerollLimit
+    #[serde(default)]
+    pub penalty: bool,
+    /// Took effect on the square the routes ended on, before any move it makes
+    #[serde(default)]
+    pub special: Option<Special>,
+}
+
+/// Every roll of the game in the order they happened, e.g. to write out with to_json_lines
+pub fn game_events(game: &Game) -> Vec<Event> {
+    let mut events: Vec<Event> = game
+        .players
+        .iter()
+        .flat_map(|p| p.trace.iter().flatten().cloned())
+        .collect();
+    // Seats take turns in order, and a stable sort keeps the rolls of a turn in order
+    events.sort_by_key(|e| (e.turn, e.player));
+    events
+}
+
+/// One JSON object per line
+pub fn to_json_lines(events: &[Event]) -> String {
+    events
+        .iter()
+        .map(|e| serde_json::to_string(e).unwrap() + "\n")
+        .collect()
+}
+
+/// Read back to_json_lines, skipping blank lines
+pub fn from_json_lines(text: &str) -> Result<Vec<Event>, serde_json::Error> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+/// Why a trace doesn't replay
+#[derive(Debug, PartialEq)]
+pub enum ReplayError {
+    /// The trace can't come from a game with these rules
+    BadTrace(String),
+    /// The first roll that didn't come out the same as the trace
+    Diverged {
+        /// Seat in the game
+        player: usize,
+        /// The player's turn, from 1
+        turn: usize,
+        /// The player's roll, from 1
+        roll: usize,
+    },
+}
+impl std::error::Error for ReplayError {}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::BadTrace(s) => write!(f, "Bad trace: {}", s),
+            ReplayError::Diverged { player, turn, roll } => write!(
+                f,
+                "Replay doesn't match the trace for player {}, turn {}, roll {}",
+                player, turn, roll
+            ),
+        }
+    }
+}
+
+/// Play the recorded dice again through MockDie, and check every roll comes out the same.
+/// Lost turns have no rolls, so they're only checked by the turn numbers skipping ahead.
+/// Returns the players as they were at the end of the trace
+pub fn replay(board: &Board, rules: &Rules, events: &[Event]) -> Result<Vec<Sim>, ReplayError> {
+    if let Some(e) = events.iter().find(|e| e.player >= rules.players) {
+        return Err(ReplayError::BadTrace(format!(
+            "Player {} in a game of {}",
+            e.player, rules.players
+        )));
+    }
+    if let Some(e) = events.iter().find(|e| e.dice.len() != rules.die.count) {
+        return Err(ReplayError::BadTrace(format!(
+            "Roll {} of player {} has {} dice, the rules roll {}",
+            e.roll,
+            e.player,
+            e.dice.len(),
+            rules.die.count
+        )));
+    }
+    // Which way each random route went, as the index Roll::choose picked
+    let mut choices: Vec<Vec<usize>> = vec![];
+    for e in events {
+        let mut chosen = vec![];
+        for &(from, to) in e.routes.iter() {
+            let Some(route) = board.random_routes.get(&from) else {
+                continue;
+            };
+            match route
+                .outcomes(from)
+                .iter()
+                .position(|&(square, _)| square == to)
+            {
+                Some(i) => chosen.push(i),
+                None => {
+                    return Err(ReplayError::BadTrace(format!(
+                        "Roll {} of player {} goes from {} to {}, the random route can't",
+                        e.roll, e.player, from, to
+                    )))
+                }
+            }
+        }
+        choices.push(chosen);
+    }
+    let players: Vec<Sim> = (0..rules.players)
+        .map(|seat| {
+            // Each roll's dice come first, then its choices. MockDie pops from the end
+            let mut dice: Vec<usize> = events
+                .iter()
+                .zip(&choices)
+                .filter(|(e, _)| e.player == seat)
+                .flat_map(|(e, chosen)| e.dice.iter().chain(chosen).copied())
+                .collect();
+            dice.reverse();
+            let mut sim = Sim::with_rules(
+                board.clone(),
+                rules.clone(),
+                Box::new(MockDie {
+                    queued_results: dice,
+                }),
+            );
+            sim.seat = seat;
+            sim.record_trace();
+            sim
+        })
+        .collect();
+
+    // Seats take turns in order as in Game::run, so a shared board changes at the same points.
+    // Step through one recorded turn at a time, so the dice can't run out mid-turn
+    let mut game = Game::new(players);
+    let diverged = |event: &Event| ReplayError::Diverged {
+        player: event.player,
+        turn: event.turn,
+        roll: event.roll,
+    };
+    let mut rest = events;
+    'rounds: loop {
+        for seat in 0..game.players.len() {
+            let Some(first) = rest.first() else {
+                break 'rounds;
+            };
+            if game.players[seat].loses_next_turn() {
+                game.take_turn(seat, Sim::turn);
+                continue;
+            }
+            if (first.player, first.turn) != (seat, game.players[seat].turn_count + 1) {
+                return Err(diverged(first));
+            }
+            let turn_length = rest
+                .iter()
+                .take_while(|e| (e.player, e.turn) == (first.player, first.turn))
+                .count();
+            let (turn, later) = rest.split_at(turn_length);
+            rest = later;
+            game.take_turn(seat, |sim| {
+                sim.start_turn();
+                let mut again = true;
+                for event in turn {
+                    if !again || sim.has_won() {
+                        // The turn should have ended before this roll
+                        return Err(diverged(event));
+                    }
+                    again = sim.turn_roll();
+                    if sim.trace.as_ref().and_then(|t| t.last()) != Some(event) {
+                        return Err(diverged(event));
+                    }
+                }
+                sim.end_turn();
+                if again && !sim.has_won() {
+                    // The turn should have had another roll
+                    return Err(diverged(&turn[turn.len() - 1]));
+                }
+                Ok(())
+            })?;
+            if game.players[seat].take_swap() {
+                swap_with_leader(&mut game.players, seat);
+            }
+        }
+    }
+    game.share_board();
+    Ok(game.players)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::trace_game;
+    use crate::boards::{canon_board, RandomRoute};
+    use crate::layout::BoardChange;
+
+    #[test]
+    fn test_trace_events() {
+        let board = Board::new(20, [(3, 11), (11, 15), (17, 4)].into()).unwrap();
+        let mut sim = Sim::new(
+            board,
+            Box::new(MockDie {
+                queued_results: vec![6, 5, 6, 3],
+            }),
+        );
+        sim.record_trace();
+        sim.turn();
+        sim.turn();
+        let trace = sim.trace.unwrap();
+        assert_eq!(trace.len(), 3);
+        assert_eq!(
+            trace[0],
+            Event {
+                player: 0,
+                turn: 1,
+                roll: 1,
+                dice: vec![3],
+                from: 0,
+                rolled: 3,
+                routes: vec![(3, 11), (11, 15)],
+                to: 15,
+                luck: Luck::Lucky,
+                penalty: false,
+                special: None,
+            }
+        );
+        // Overshot with a 6, so stayed put and went again
+        assert_eq!((trace[1].turn, trace[1].dice[0], trace[1].to), (2, 6, 15));
+        assert_eq!((trace[2].turn, trace[2].rolled, trace[2].to), (2, 20, 20));
+    }
+
+    #[test]
+    fn test_no_trace_by_default() {
+        let game = crate::batch::play_game(&canon_board(), &Rules::default(), 3);
+        assert!(game.players[0].trace.is_none());
+    }
+
+    #[test]
+    fn test_json_lines_round_trip() {
+        let rules = Rules {
+            players: 2,
+            ..Rules::default()
+        };
+        let (_, events) = trace_game(&canon_board(), &rules, 11);
+        let text = to_json_lines(&events);
+        assert_eq!(text.lines().count(), events.len());
+        assert_eq!(from_json_lines(&text).unwrap(), events);
+    }
+
+    #[test]
+    fn test_replay() {
+        let rules = Rules {
+            players: 2,
+            ..Rules::default()
+        };
+        let (game, events) = trace_game(&canon_board(), &rules, 11);
+        let players = replay(&canon_board(), &rules, &events).unwrap();
+        for (replayed, played) in players.iter().zip(&game.players) {
+            assert_eq!(replayed.roll_count, played.roll_count);
+            assert_eq!(replayed.turn_count, played.turn_count);
+            assert_eq!(replayed.has_won(), played.has_won());
+            assert_eq!(replayed.longest_turn, played.longest_turn);
+        }
+    }
+
+    #[test]
+    fn test_replay_specials() {
+        let specials = [
+            (5, Special::LoseTurn),
+            (8, Special::ExtraRoll),
+            (12, Special::Backwards),
+            (15, Special::BackToStart),
+            (21, Special::SwapWithLeader),
+        ];
+        let board = Board::with_specials(40, [(3, 11)].into(), specials.into()).unwrap();
+        let rules = Rules {
+            players: 3,
+            ..Rules::default()
+        };
+        for seed in 0..20 {
+            let (game, events) = trace_game(&board, &rules, seed);
+            let players = replay(&board, &rules, &events).unwrap();
+            for (replayed, played) in players.iter().zip(&game.players) {
+                assert_eq!(replayed.position(), played.position());
+                assert_eq!(replayed.turns_lost, played.turns_lost);
+                assert_eq!(replayed.swaps, played.swaps);
+            }
+        }
+    }
+
+    #[test]
+    fn test_replay_random_routes() {
+        let random = RandomRoute {
+            chance: 0.5,
+            to: vec![(1, 1.0), (20, 1.0)],
+        };
+        let board = Board::new(40, [(3, 11)].into())
+            .unwrap()
+            .with_random_routes([(6, random)].into())
+            .unwrap();
+        let rules = Rules {
+            players: 2,
+            ..Rules::default()
+        };
+        for seed in 0..20 {
+            let (game, events) = trace_game(&board, &rules, seed);
+            let players = replay(&board, &rules, &events).unwrap();
+            for (replayed, played) in players.iter().zip(&game.players) {
+                assert_eq!(replayed.position(), played.position());
+                assert_eq!(replayed.route_uses, played.route_uses);
+            }
+        }
+        // Nowhere the random route goes
+        let took_route = |e: &Event| e.routes.first().is_some_and(|r| r.0 == 6);
+        let mut events = (0..)
+            .map(|seed| trace_game(&board, &rules, seed).1)
+            .find(|events| events.iter().any(took_route))
+            .unwrap();
+        let event = events.iter_mut().find(|e| took_route(e)).unwrap();
+        event.routes[0].1 = 7;
+        assert!(matches!(
+            replay(&board, &rules, &events),
+            Err(ReplayError::BadTrace(_))
+        ));
+    }
+
+    #[test]
+    fn test_replay_board_changes() {
+        // Lost turns still count towards the round
+        let specials = [(5, Special::LoseTurn)];
+        let board = Board::with_specials(40, [(3, 11), (30, 12)].into(), specials.into()).unwrap();
+        let rules = Rules {
+            players: 2,
+            board_changes: vec![
+                BoardChange::Break { from: 3, after: 1 },
+                BoardChange::Move {
+                    from: 30,
+                    every: 2,
+                    positions: vec![(25, 9)],
+                },
+            ],
+            ..Rules::default()
+        };
+        for seed in 0..20 {
+            let (game, events) = trace_game(&board, &rules, seed);
+            let players = replay(&board, &rules, &events).unwrap();
+            for (replayed, played) in players.iter().zip(&game.players) {
+                assert_eq!(replayed.position(), played.position());
+                assert_eq!(replayed.board().routes, game.players[0].board().routes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_replay_diverged() {
+        let (_, mut events) = trace_game(&canon_board(), &Rules::default(), 11);
+        events[2].to += 1;
+        let (turn, roll) = (events[2].turn, events[2].roll);
+        assert_eq!(
+            replay(&canon_board(), &Rules::default(), &events).err(),
+            Some(ReplayError::Diverged {
+                player: 0,
+                turn,
+                roll
+            })
+        );
+        // Different rules, a 6 no longer rolls again
+        let (_, events) = trace_game(&canon_board(), &Rules::default(), 11);
+        let rules = Rules {
+            die: crate::dice::Die {
+                reroll_on: crate::dice::RerollOn::Never,
+                ..Default::default()
+            },
+            ..Rules::default()
+        };
+        assert!(matches!(
+            replay(&canon_board(), &rules, &events),
+            Err(ReplayError::Diverged { .. })
+        ));
+    }
+}